@@ -1,12 +1,25 @@
 use crate::{
-    command::CanvasCommand,
+    clock::Ticks,
+    command::{CanvasCommand, RemoveTarget},
+    editor::Editor,
+    frame::{EmbeddedFrame, Frame},
+    marker::{MarkKind, Marker},
     query::{CanvasQuery, CanvasQueryValue},
 };
 use orfail::OrFail;
-use pati::{Color, ImageCommand, Point, VersionedImage};
-use std::num::NonZeroU8;
+use pati::{Color, ImageCommand, PatchEntry, Point, VersionedImage};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    num::{NonZeroU16, NonZeroU8},
+};
+
+pub const METADATA_BACKGROUND_COLOR: &str = "patica.background_color";
+pub const METADATA_BRUSH_COLOR: &str = "patica.brush_color";
+pub const METADATA_FRAME_PREFIX: &str = "patica.frame.";
+pub const METADATA_GRID: &str = "patica.grid";
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Canvas {
     image: VersionedImage,
     cursor: Point,
@@ -15,7 +28,10 @@ pub struct Canvas {
     background_color: Color,
     scale: Scale,
     fps: Fps,
-    // TODO: fsm(or mode), frames, ticks
+    frames: BTreeMap<String, EmbeddedFrame>,
+    grid: Option<Grid>,
+    ticks: Ticks,
+    mode: Mode,
     quit: bool,
 }
 
@@ -52,10 +68,77 @@ impl Canvas {
         self.fps.0
     }
 
+    pub fn frames(&self) -> &BTreeMap<String, EmbeddedFrame> {
+        &self.frames
+    }
+
+    /// Gets the grid recorded as the `patica.grid` metadata.
+    pub fn grid(&self) -> Option<Grid> {
+        self.grid
+    }
+
+    pub fn frames_mut(&mut self) -> &mut BTreeMap<String, EmbeddedFrame> {
+        &mut self.frames
+    }
+
+    pub fn ticks(&self) -> Ticks {
+        self.ticks
+    }
+
+    pub fn mode(&self) -> CanvasMode {
+        match self.mode {
+            Mode::Neutral => CanvasMode::Neutral,
+            Mode::Marking(_) => CanvasMode::Marking,
+            Mode::Editing(_) => CanvasMode::Editing,
+            Mode::Playing => CanvasMode::Playing,
+        }
+    }
+
+    /// Gets the editor if the canvas is in the editing mode.
+    pub fn editor(&self) -> Option<&Editor> {
+        if let Mode::Editing(editor) = &self.mode {
+            Some(editor)
+        } else {
+            None
+        }
+    }
+
+    /// Gets the marker if the canvas is in the marking mode.
+    pub fn marker(&self) -> Option<&Marker> {
+        if let Mode::Marking(marker) = &self.mode {
+            Some(marker)
+        } else {
+            None
+        }
+    }
+
     pub fn quit(&self) -> bool {
         self.quit
     }
 
+    /// Gets the pixels in the given rectangle (inclusive) that are shown in the editor.
+    ///
+    /// The pixels of the frames visible at the current ticks come first,
+    /// followed by the pixels of the image that are drawn over them.
+    pub fn visible_pixels(
+        &self,
+        start: Point,
+        end: Point,
+    ) -> impl '_ + Iterator<Item = (Point, Color)> {
+        let in_range =
+            move |p: &Point| start.x <= p.x && p.x <= end.x && start.y <= p.y && p.y <= end.y;
+        self.frames
+            .values()
+            .filter(|f| f.frame.is_visible(self.ticks))
+            .flat_map(move |f| {
+                f.pixels
+                    .range(start..=end)
+                    .filter(move |(p, _)| in_range(p))
+                    .map(|(p, c)| (*p, *c))
+            })
+            .chain(self.image.range_pixels(start..=end))
+    }
+
     pub fn query(&self, query: &CanvasQuery) -> CanvasQueryValue {
         match query {
             CanvasQuery::Cursor => CanvasQueryValue::Cursor(self.cursor),
@@ -66,24 +149,212 @@ impl Canvas {
             }
             CanvasQuery::Scale => CanvasQueryValue::Scale(self.scale.0),
             CanvasQuery::Fps => CanvasQueryValue::Fps(self.fps.0),
+            CanvasQuery::Anchors => CanvasQueryValue::Anchors(self.image.anchors().clone()),
+            CanvasQuery::MetadataKeys => {
+                CanvasQueryValue::MetadataKeys(self.image.metadata().keys().cloned().collect())
+            }
+            CanvasQuery::Metadata(name) => {
+                CanvasQueryValue::Metadata(self.image.metadata().get(name).cloned())
+            }
+            CanvasQuery::Pixel(point) => CanvasQueryValue::Pixel(self.image.get_pixel(*point)),
+            CanvasQuery::Bounds => CanvasQueryValue::Bounds(self.image.bounding_box()),
+            CanvasQuery::Version => CanvasQueryValue::Version(self.image.version()),
+            CanvasQuery::ColorHistogram => {
+                let mut histogram = BTreeMap::<Color, usize>::new();
+                for color in self.image.pixels().values() {
+                    *histogram.entry(*color).or_default() += 1;
+                }
+                let mut histogram = histogram.into_iter().collect::<Vec<_>>();
+                histogram.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+                CanvasQueryValue::ColorHistogram(histogram)
+            }
         }
     }
 
     pub fn command(&mut self, command: &CanvasCommand) -> orfail::Result<()> {
         match command {
             CanvasCommand::Move(c) => self.handle_move(*c).or_fail()?,
+            CanvasCommand::Jump(c) => self.handle_jump(c).or_fail()?,
+            CanvasCommand::Anchor(c) => self.handle_anchor(c).or_fail()?,
+            CanvasCommand::Center => self.camera = self.cursor,
             CanvasCommand::Image(c) => self.handle_image_command(c).or_fail()?,
             CanvasCommand::Scale(c) => self.handle_scale(*c).or_fail()?,
+            CanvasCommand::Tick(c) => self.ticks.tick_delta(*c),
             CanvasCommand::Quit => self.quit = true,
+            CanvasCommand::Draw => self.handle_draw().or_fail()?,
+            CanvasCommand::Erase => self.handle_erase().or_fail()?,
+            CanvasCommand::Dip(c) => self.handle_dip(*c).or_fail()?,
+            CanvasCommand::Pick => self.handle_pick().or_fail()?,
+            CanvasCommand::Mark(c) => self.handle_mark(*c),
+            CanvasCommand::Copy => self.handle_copy(false).or_fail()?,
+            CanvasCommand::Cut => self.handle_copy(true).or_fail()?,
+            CanvasCommand::Play => self.mode = Mode::Playing,
+            CanvasCommand::Cancel => self.mode = Mode::Neutral,
+            CanvasCommand::Import(c) => self.handle_import(c),
+            CanvasCommand::Flip(c) => self.editor_mut().or_fail()?.apply_flip(*c),
+            CanvasCommand::Rotate => self.editor_mut().or_fail()?.apply_rotate(),
+            CanvasCommand::Embed(c) => self.handle_embed(c).or_fail()?,
+            CanvasCommand::Remove(c) => self.handle_remove(c).or_fail()?,
         }
         Ok(())
     }
 
+    /// Applies a command read from a file.
+    ///
+    /// Unlike [`Canvas::command()`], a metadata value that cannot be interpreted
+    /// (e.g., a malformed `patica.grid`) is not an error: the command is still recorded
+    /// in the image, but the previous value stays in effect.
+    pub fn replay(&mut self, command: &ImageCommand) {
+        if let ImageCommand::Put { name, value } = command {
+            self.handle_metadata(name, value).ok();
+        }
+        self.image.apply(command);
+    }
+
     fn handle_move(&mut self, delta: Point) -> orfail::Result<()> {
-        self.cursor = self.cursor + delta;
+        self.set_cursor(self.cursor + delta);
+        Ok(())
+    }
+
+    fn handle_jump(&mut self, anchor: &str) -> orfail::Result<()> {
+        let point = self
+            .image
+            .anchors()
+            .get(anchor)
+            .copied()
+            .or_fail_with(|()| format!("No such anchor: {anchor}"))?;
+        self.set_cursor(point);
         Ok(())
     }
 
+    fn set_cursor(&mut self, point: Point) {
+        self.cursor = point;
+        // The marker is taken out during the update because it refers to the canvas.
+        self.mode = match std::mem::replace(&mut self.mode, Mode::Neutral) {
+            Mode::Marking(mut marker) => {
+                marker.handle_move(self);
+                Mode::Marking(marker)
+            }
+            mode => mode,
+        };
+    }
+
+    fn handle_anchor(&mut self, anchor: &str) -> orfail::Result<()> {
+        let command = ImageCommand::anchor(anchor, Some(self.cursor));
+        self.handle_image_command(&command).or_fail()
+    }
+
+    fn handle_draw(&mut self) -> orfail::Result<()> {
+        let cursor = self.cursor;
+        let color = self.brush_color;
+        let command = match &self.mode {
+            Mode::Neutral | Mode::Playing => {
+                ImageCommand::draw_pixels(std::iter::once((cursor, color)))
+            }
+            Mode::Marking(marker) => {
+                ImageCommand::draw_pixels(marker.marked_points().map(|p| (p, color)))
+            }
+            Mode::Editing(editor) => {
+                ImageCommand::draw_pixels(editor.pixels().map(|(p, c)| (p + cursor, c)))
+            }
+        };
+        self.handle_image_command(&command).or_fail()
+    }
+
+    fn handle_erase(&mut self) -> orfail::Result<()> {
+        let points = match &self.mode {
+            Mode::Neutral | Mode::Playing => vec![self.cursor],
+            Mode::Marking(marker) => marker.marked_points().collect(),
+            Mode::Editing(_) => return Err(orfail::Failure::new("Cannot erase while editing")),
+        };
+        let command = ImageCommand::patch(vec![PatchEntry::erase(points)]);
+        self.handle_image_command(&command).or_fail()?;
+        if matches!(self.mode, Mode::Marking(_)) {
+            self.mode = Mode::Neutral;
+        }
+        Ok(())
+    }
+
+    fn handle_dip(&mut self, color: Color) -> orfail::Result<()> {
+        let command =
+            ImageCommand::put(METADATA_BRUSH_COLOR, serde_json::to_value(color).or_fail()?);
+        self.handle_image_command(&command).or_fail()
+    }
+
+    fn handle_pick(&mut self) -> orfail::Result<()> {
+        let cursor = self.cursor;
+        let color = self
+            .visible_pixels(cursor, cursor)
+            .last()
+            .map(|(_, c)| c)
+            .or_fail_with(|()| "No pixel at the cursor".to_owned())?;
+        self.handle_dip(color).or_fail()
+    }
+
+    fn handle_mark(&mut self, kind: MarkKind) {
+        self.mode = Mode::Marking(Marker::new(kind, self));
+    }
+
+    fn handle_copy(&mut self, cut: bool) -> orfail::Result<()> {
+        let Mode::Marking(marker) = &self.mode else {
+            return Err(orfail::Failure::new("Not in the marking mode"));
+        };
+        let points = marker.marked_points().collect::<Vec<_>>();
+        let pixels = points
+            .iter()
+            .filter_map(|&p| self.image.get_pixel(p).map(|c| (p - self.cursor, c)))
+            .collect();
+        if cut {
+            let command = ImageCommand::patch(vec![PatchEntry::erase(points)]);
+            self.handle_image_command(&command).or_fail()?;
+        }
+        self.mode = Mode::Editing(Editor::new(pixels));
+        Ok(())
+    }
+
+    fn handle_embed(&mut self, frame: &Frame) -> orfail::Result<()> {
+        let frame = EmbeddedFrame::new(frame.clone(), self.cursor);
+        let command = ImageCommand::put(
+            format!("{METADATA_FRAME_PREFIX}{}", frame.frame.name),
+            serde_json::to_value(&frame).or_fail()?,
+        );
+        self.handle_image_command(&command).or_fail()
+    }
+
+    fn handle_remove(&mut self, target: &RemoveTarget) -> orfail::Result<()> {
+        let command = match target {
+            RemoveTarget::Anchor(name) => {
+                self.image
+                    .anchors()
+                    .contains_key(name)
+                    .or_fail_with(|()| format!("No such anchor: {name}"))?;
+                ImageCommand::anchor(name.clone(), None)
+            }
+            RemoveTarget::Frame(name) => {
+                self.frames
+                    .contains_key(name)
+                    .or_fail_with(|()| format!("No such frame: {name}"))?;
+                ImageCommand::put(
+                    format!("{METADATA_FRAME_PREFIX}{name}"),
+                    serde_json::Value::Null,
+                )
+            }
+        };
+        self.handle_image_command(&command).or_fail()
+    }
+
+    fn handle_import(&mut self, pixels: &[(Point, Color)]) {
+        self.mode = Mode::Editing(Editor::new(pixels.iter().copied().collect()));
+    }
+
+    fn editor_mut(&mut self) -> orfail::Result<&mut Editor> {
+        if let Mode::Editing(editor) = &mut self.mode {
+            Ok(editor)
+        } else {
+            Err(orfail::Failure::new("Not in the editing mode"))
+        }
+    }
+
     fn handle_scale(&mut self, delta: i8) -> orfail::Result<()> {
         let scale = (self.scale.0.get() as i8 + delta).clamp(1, 100);
         self.scale = Scale(NonZeroU8::new(scale as u8).expect("unreachable"));
@@ -91,14 +362,78 @@ impl Canvas {
     }
 
     fn handle_image_command(&mut self, command: &ImageCommand) -> orfail::Result<()> {
+        if let ImageCommand::Put { name, value } = command {
+            self.handle_metadata(name, value).or_fail()?;
+        }
         self.image.apply(command);
-        if let ImageCommand::Put { .. } = command {
-            // TODO
+        Ok(())
+    }
+
+    fn handle_metadata(&mut self, name: &str, value: &serde_json::Value) -> orfail::Result<()> {
+        if name == METADATA_BACKGROUND_COLOR {
+            self.background_color = parse_color(value).or_fail()?;
+        } else if name == METADATA_BRUSH_COLOR {
+            self.brush_color = parse_color(value).or_fail()?;
+        } else if name == METADATA_GRID {
+            self.grid = serde_json::from_value(value.clone()).or_fail()?;
+        } else if let Some(frame_name) = name.strip_prefix(METADATA_FRAME_PREFIX) {
+            if value.is_null() {
+                self.frames.remove(frame_name);
+            } else {
+                let frame: EmbeddedFrame = serde_json::from_value(value.clone()).or_fail()?;
+                self.frames.insert(frame_name.to_owned(), frame);
+            }
         }
         Ok(())
     }
 }
 
+/// Grid drawn over the canvas in the editor (lines are put on multiples of the cell size).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grid {
+    pub width: NonZeroU16,
+    pub height: NonZeroU16,
+
+    #[serde(default = "Grid::default_color")]
+    pub color: Color,
+}
+
+impl Grid {
+    fn default_color() -> Color {
+        Color::rgba(0, 0, 0, 32)
+    }
+}
+
+fn parse_color(value: &serde_json::Value) -> orfail::Result<Color> {
+    if value.is_null() {
+        Ok(Color::default())
+    } else {
+        serde_json::from_value(value.clone()).or_fail()
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+enum Mode {
+    #[default]
+    Neutral,
+    Marking(Marker),
+    Editing(Editor),
+    Playing,
+}
+
+/// Kind of the current mode of a [`Canvas`].
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum CanvasMode {
+    #[default]
+    Neutral,
+    Marking,
+    Editing,
+    Playing,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Scale(NonZeroU8);
 
@@ -116,3 +451,146 @@ impl Default for Fps {
         Self(NonZeroU8::new(30).expect("unreachable"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw_at(canvas: &mut Canvas, point: Point, color: Color) {
+        canvas.command(&CanvasCommand::Dip(color)).unwrap();
+        canvas
+            .command(&CanvasCommand::Move(point - canvas.cursor()))
+            .unwrap();
+        canvas.command(&CanvasCommand::Draw).unwrap();
+    }
+
+    #[test]
+    fn dip_and_pick() {
+        let mut canvas = Canvas::new();
+        let red = Color::rgb(255, 0, 0);
+        assert!(canvas.command(&CanvasCommand::Pick).is_err());
+
+        draw_at(&mut canvas, Point::new(1, 1), red);
+        canvas
+            .command(&CanvasCommand::Dip(Color::rgb(0, 0, 255)))
+            .unwrap();
+        assert_eq!(canvas.brush_color(), Color::rgb(0, 0, 255));
+        canvas.command(&CanvasCommand::Pick).unwrap();
+        assert_eq!(canvas.brush_color(), red);
+    }
+
+    #[test]
+    fn mark_and_erase() {
+        let mut canvas = Canvas::new();
+        let red = Color::rgb(255, 0, 0);
+        for point in [Point::new(0, 0), Point::new(1, 1), Point::new(3, 3)] {
+            draw_at(&mut canvas, point, red);
+        }
+
+        // Erase the pixel at the cursor.
+        canvas.command(&CanvasCommand::Erase).unwrap();
+        assert_eq!(canvas.image().get_pixel(Point::new(3, 3)), None);
+
+        // The marked region follows the cursor.
+        canvas
+            .command(&CanvasCommand::Move(Point::new(-3, -3)))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Mark(MarkKind::Rectangle))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Move(Point::new(1, 1)))
+            .unwrap();
+        assert_eq!(canvas.mode(), CanvasMode::Marking);
+        assert_eq!(canvas.marker().unwrap().marked_points().count(), 4);
+
+        // Erasing the marked pixels ends the marking mode.
+        canvas.command(&CanvasCommand::Erase).unwrap();
+        assert_eq!(canvas.mode(), CanvasMode::Neutral);
+        assert!(canvas.image().pixels().is_empty());
+    }
+
+    #[test]
+    fn copy_and_cut() {
+        let mut canvas = Canvas::new();
+        let red = Color::rgb(255, 0, 0);
+        draw_at(&mut canvas, Point::new(0, 0), red);
+        draw_at(&mut canvas, Point::new(1, 0), red);
+        assert!(canvas.command(&CanvasCommand::Copy).is_err());
+
+        canvas
+            .command(&CanvasCommand::Move(Point::new(-1, 0)))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Mark(MarkKind::Line))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Move(Point::new(1, 0)))
+            .unwrap();
+        canvas.command(&CanvasCommand::Copy).unwrap();
+        assert_eq!(canvas.mode(), CanvasMode::Editing);
+        assert_eq!(canvas.image().pixels().len(), 2);
+
+        // The copied pixels are relative to the cursor and stay in the editing mode after moves.
+        let pixels = |canvas: &Canvas| canvas.editor().unwrap().pixels().collect::<Vec<_>>();
+        assert_eq!(
+            pixels(&canvas),
+            [(Point::new(-1, 0), red), (Point::new(0, 0), red)]
+        );
+        canvas
+            .command(&CanvasCommand::Move(Point::new(0, 2)))
+            .unwrap();
+        assert_eq!(canvas.mode(), CanvasMode::Editing);
+        assert!(canvas.command(&CanvasCommand::Erase).is_err());
+        canvas.command(&CanvasCommand::Draw).unwrap();
+        assert_eq!(canvas.image().get_pixel(Point::new(0, 2)), Some(red));
+
+        // Cutting erases the marked pixels.
+        canvas
+            .command(&CanvasCommand::Move(Point::new(0, -2)))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Mark(MarkKind::Line))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Move(Point::new(-1, 0)))
+            .unwrap();
+        canvas.command(&CanvasCommand::Cut).unwrap();
+        assert_eq!(canvas.mode(), CanvasMode::Editing);
+        assert_eq!(canvas.image().pixels().len(), 2);
+        assert_eq!(canvas.image().get_pixel(Point::new(0, 0)), None);
+    }
+
+    #[test]
+    fn anchor_jump_and_center() {
+        let mut canvas = Canvas::new();
+        assert!(canvas
+            .command(&CanvasCommand::Jump("head".to_owned()))
+            .is_err());
+
+        canvas
+            .command(&CanvasCommand::Move(Point::new(3, 4)))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Anchor("head".to_owned()))
+            .unwrap();
+        assert_eq!(canvas.image().anchors()["head"], Point::new(3, 4));
+
+        // The marker follows the jump.
+        canvas
+            .command(&CanvasCommand::Move(Point::new(-3, -4)))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Mark(MarkKind::Line))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Jump("head".to_owned()))
+            .unwrap();
+        assert_eq!(canvas.cursor(), Point::new(3, 4));
+        assert_eq!(canvas.marker().unwrap().marked_points().count(), 5);
+
+        assert_eq!(canvas.camera(), Point::ORIGIN);
+        canvas.command(&CanvasCommand::Center).unwrap();
+        assert_eq!(canvas.camera(), Point::new(3, 4));
+    }
+}
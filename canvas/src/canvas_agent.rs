@@ -4,15 +4,15 @@ use crate::{
 };
 use orfail::OrFail;
 use serde::{Deserialize, Serialize};
+use std::io::Write;
 use std::{
-    collections::HashMap,
-    io::{BufRead, BufReader},
+    collections::{HashMap, VecDeque},
+    io::{BufRead, BufReader, Read},
     net::{SocketAddr, TcpListener, TcpStream},
 };
-use std::{
-    io::{Read, Write},
-    time::Duration,
-};
+
+/// Maximum number of bytes queued for a client that does not receive its responses.
+const MAX_PENDING_OUTPUT: usize = 64 * 1024 * 1024;
 
 #[derive(Debug)]
 pub struct CanvasAgentServer {
@@ -36,11 +36,8 @@ impl CanvasAgentServer {
     pub fn poll_request(&mut self) -> orfail::Result<Option<(SocketAddr, CanvasAgentRequest)>> {
         match self.listener.accept() {
             Ok((stream, addr)) => {
-                stream
-                    .set_read_timeout(Some(Duration::from_secs(0)))
-                    .or_fail()?;
-                self.clients
-                    .insert(addr, ClientState::new(stream).or_fail()?);
+                stream.set_nonblocking(true).or_fail()?;
+                self.clients.insert(addr, ClientState::new(stream));
             }
             Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
             Err(e) => return Err(e).or_fail(),
@@ -66,7 +63,7 @@ impl CanvasAgentServer {
         response: impl Serialize,
     ) -> orfail::Result<()> {
         let client = self.clients.get_mut(&addr).or_fail()?;
-        if send(&mut client.writer, response).is_err() {
+        if client.send(response).is_err() {
             self.clients.remove(&addr);
         }
         Ok(())
@@ -77,65 +74,134 @@ impl CanvasAgentServer {
     }
 }
 
+/// Connection to a client.
+///
+/// The stream is non-blocking so that a slow client never stalls the editor:
+/// partial requests and unsent responses are buffered and processed on the next poll.
 #[derive(Debug)]
 struct ClientState {
-    reader: BufReader<TcpStream>,
-    writer: TcpStream,
-    line: String,
+    stream: TcpStream,
+
+    /// Received bytes that do not form a complete line yet.
+    buf: Vec<u8>,
+
+    /// Response bytes that have not been written to the stream yet.
+    output: VecDeque<u8>,
+    handshaked: bool,
 }
 
 impl ClientState {
-    fn new(writer: TcpStream) -> orfail::Result<Self> {
-        Ok(Self {
-            reader: BufReader::new(writer.try_clone().or_fail()?),
-            writer,
-            line: String::new(),
-        })
+    fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            buf: Vec::new(),
+            output: VecDeque::new(),
+            handshaked: false,
+        }
     }
 
     fn poll(&mut self) -> Result<Option<CanvasAgentRequest>, ()> {
-        match self.reader.read_line(&mut self.line) {
-            Ok(0) => Err(()),
-            Ok(_) => {
-                let request: CanvasAgentRequest =
-                    serde_json::from_str(&self.line).map_err(|_| ())?;
-                self.line.clear();
-                Ok(Some(request))
+        self.flush()?;
+        loop {
+            while let Some(line) = self.take_line()? {
+                if self.handshaked {
+                    let request = serde_json::from_str(&line).map_err(|_| ())?;
+                    return Ok(Some(request));
+                }
+                let handshake: Handshake = serde_json::from_str(&line).map_err(|_| ())?;
+                let result = handshake.validate();
+                self.send(&result).map_err(|_| ())?;
+                result.map_err(|_| ())?;
+                self.handshaked = true;
+            }
+
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(()),
+                Ok(size) => self.buf.extend_from_slice(&chunk[..size]),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => return Err(()),
             }
-            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
-            Err(_) => Err(()),
         }
     }
+
+    /// Takes the first complete line out of the buffer
+    /// (a multibyte character split across reads is decoded only once the line is complete).
+    fn take_line(&mut self) -> Result<Option<String>, ()> {
+        let Some(end) = self.buf.iter().position(|&b| b == b'\n') else {
+            return Ok(None);
+        };
+        let line = self.buf.drain(..=end).collect::<Vec<_>>();
+        String::from_utf8(line).map(Some).map_err(|_| ())
+    }
+
+    /// Queues a response and writes as much of the queue as possible without blocking.
+    fn send(&mut self, value: impl Serialize) -> orfail::Result<()> {
+        let mut line = serde_json::to_vec(&value).or_fail()?;
+        line.push(b'\n');
+        self.output.extend(line);
+        (self.output.len() <= MAX_PENDING_OUTPUT)
+            .or_fail_with(|()| "Too many pending responses for the client".to_owned())?;
+        self.flush()
+            .map_err(|()| orfail::Failure::new("Failed to send a response to the client"))
+    }
+
+    fn flush(&mut self) -> Result<(), ()> {
+        while !self.output.is_empty() {
+            let (chunk, _) = self.output.as_slices();
+            match self.stream.write(chunk) {
+                Ok(0) => return Err(()),
+                Ok(size) => {
+                    self.output.drain(..size);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(_) => return Err(()),
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
 pub struct CanvasAgent {
-    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
 }
 
 impl CanvasAgent {
     pub fn connect(port: u16) -> orfail::Result<Self> {
-        let mut stream = TcpStream::connect(("127.0.0.1", port)).or_fail()?;
+        let writer = TcpStream::connect(("127.0.0.1", port))
+            .or_fail_with(|e| format!("Failed to connect to the editor (port={port}): {e}"))?;
+        let mut this = Self {
+            reader: BufReader::new(writer.try_clone().or_fail()?),
+            writer,
+        };
 
         // Handshake
-        send(&mut stream, Handshake::new()).or_fail()?;
-        recv::<orfail::Result<()>>(&mut stream)
+        send(&mut this.writer, Handshake::new()).or_fail()?;
+        recv::<orfail::Result<()>>(&mut this.reader)
             .or_fail()?
             .or_fail()?;
 
-        Ok(Self { stream })
+        Ok(this)
     }
 
-    pub fn command(&mut self, command: CanvasCommand) -> orfail::Result<()> {
+    /// Sends the given command to the editor.
+    ///
+    /// The outer result reports communication errors, and the inner one reports
+    /// the error that occurred while the editor was handling the command.
+    pub fn command(&mut self, command: CanvasCommand) -> orfail::Result<orfail::Result<()>> {
         let request = CanvasAgentRequest::Command(command);
-        send(&mut self.stream, &request).or_fail()?;
-        recv(&mut self.stream).or_fail()
+        send(&mut self.writer, &request).or_fail()?;
+        recv(&mut self.reader).or_fail()
     }
 
     pub fn query(&mut self, query: CanvasQuery) -> orfail::Result<CanvasQueryValue> {
         let request = CanvasAgentRequest::Query(query);
-        send(&mut self.stream, &request).or_fail()?;
-        recv(&mut self.stream).or_fail()
+        send(&mut self.writer, &request).or_fail()?;
+        recv(&mut self.reader).or_fail()
     }
 }
 
@@ -146,8 +212,11 @@ fn send(mut writer: impl Write, value: impl Serialize) -> orfail::Result<()> {
     Ok(())
 }
 
-fn recv<T: for<'a> Deserialize<'a>>(mut reader: impl Read) -> orfail::Result<T> {
-    let value: T = serde_json::from_reader(&mut reader).or_fail()?;
+fn recv<T: for<'a> Deserialize<'a>>(mut reader: impl BufRead) -> orfail::Result<T> {
+    let mut line = String::new();
+    let size = reader.read_line(&mut line).or_fail()?;
+    (size > 0).or_fail_with(|()| "Connection closed by the editor".to_owned())?;
+    let value: T = serde_json::from_str(&line).or_fail()?;
     Ok(value)
 }
 
@@ -165,14 +234,68 @@ struct Handshake {
 }
 
 impl Handshake {
+    const MAGIC_NUMBER: &'static str = "PATICA";
+
     fn new() -> Self {
         Self {
-            magic_number: "PATICA".to_string(),
+            magic_number: Self::MAGIC_NUMBER.to_string(),
         }
     }
+
+    fn validate(&self) -> orfail::Result<()> {
+        (self.magic_number == Self::MAGIC_NUMBER)
+            .or_fail_with(|()| format!("Unexpected magic number: {:?}", self.magic_number))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[allow(dead_code)]
 struct HandshakeResult {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn split_request_and_large_response() {
+        let mut server = CanvasAgentServer::start().unwrap();
+        let port = server.port();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            send(&mut stream, Handshake::new()).unwrap();
+            recv::<orfail::Result<()>>(&mut reader).unwrap().unwrap();
+
+            // Split the request in the middle of a multibyte character.
+            let request = "{\"query\":{\"metadata\":\"\u{3042}\"}}\n".as_bytes();
+            let (first, second) = request.split_at(request.len() - 4);
+            stream.write_all(first).unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+            stream.write_all(second).unwrap();
+
+            recv::<String>(&mut reader).unwrap().len()
+        });
+
+        let (addr, request) = loop {
+            if let Some(request) = server.poll_request().unwrap() {
+                break request;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert!(matches!(
+            request,
+            CanvasAgentRequest::Query(CanvasQuery::Metadata(name)) if name == "\u{3042}"
+        ));
+
+        // A response that does not fit in the socket buffer is sent over multiple polls.
+        let response = "x".repeat(8 * 1024 * 1024);
+        server.send_response(addr, &response).unwrap();
+        while !client.is_finished() {
+            assert!(server.poll_request().unwrap().is_none());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(client.join().unwrap(), response.len());
+    }
+}
@@ -1,10 +1,15 @@
-use crate::{command::CanvasCommand, Canvas};
+use crate::{
+    command::CanvasCommand,
+    frame::{EmbeddedFiles, EmbeddedFrame},
+    Canvas,
+};
 use orfail::OrFail;
-use pati::{ImageCommandReader, ImageCommandWriter, Version};
+use pati::{ImageCommandReader, ImageCommandWriter, Point, Version};
 use std::{
+    collections::BTreeMap,
     fs::File,
     io::{BufReader, BufWriter},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 #[derive(Debug)]
@@ -13,6 +18,10 @@ pub struct CanvasFile {
     reader: ImageCommandReader<BufReader<File>>,
     writer: ImageCommandWriter<BufWriter<File>>,
     last_written_version: Version,
+    embedded_files: EmbeddedFiles,
+
+    /// Directory containing the file (relative frame paths are resolved against it).
+    base_dir: PathBuf,
 }
 
 impl CanvasFile {
@@ -23,11 +32,20 @@ impl CanvasFile {
             .create(create)
             .open(&path)
             .or_fail_with(|e| format!("Failed to open file {}: {e}", path.as_ref().display()))?;
+        let base_dir = path
+            .as_ref()
+            .canonicalize()
+            .or_fail()?
+            .parent()
+            .or_fail()?
+            .to_path_buf();
         let mut this = Self {
             canvas: Canvas::new(),
             reader: ImageCommandReader::new(BufReader::new(file.try_clone().or_fail()?)),
             writer: ImageCommandWriter::new(BufWriter::new(file)),
             last_written_version: Version::default(),
+            embedded_files: EmbeddedFiles::new(&base_dir),
+            base_dir,
         };
         this.sync().or_fail()?;
         Ok(this)
@@ -37,18 +55,71 @@ impl CanvasFile {
         &self.canvas
     }
 
+    /// Gets the number of the applied commands that have not been written to the file yet
+    /// (non-zero only if writing failed).
+    pub fn unsaved_commands(&self) -> usize {
+        self.canvas
+            .image()
+            .applied_commands(self.last_written_version)
+            .len()
+    }
+
     pub fn sync(&mut self) -> orfail::Result<()> {
         while let Some(command) = self.reader.read_command().or_fail()? {
-            self.canvas
-                .command(&CanvasCommand::Image(command))
-                .or_fail()?;
+            self.canvas.replay(&command);
         }
         self.last_written_version = self.canvas.image().version();
+        self.embedded_files.sync(self.canvas.frames_mut());
         Ok(())
     }
 
     pub fn command(&mut self, command: &CanvasCommand) -> orfail::Result<()> {
         self.sync().or_fail()?;
+        self.apply(command).or_fail()
+    }
+
+    /// Applies the given commands if no other writer has appended commands to the file
+    /// since it was at `version`.
+    ///
+    /// Unlike calling [`CanvasFile::command()`] repeatedly, the file is not re-synced
+    /// between the commands.
+    pub fn commands_at(
+        &mut self,
+        version: Version,
+        commands: &[CanvasCommand],
+    ) -> orfail::Result<()> {
+        self.sync().or_fail()?;
+        (self.canvas.image().version() == version).or_fail_with(|()| {
+            "The file has been changed by another writer; try again".to_owned()
+        })?;
+        for command in commands {
+            self.apply(command).or_fail()?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, command: &CanvasCommand) -> orfail::Result<()> {
+        let embed_command;
+        let command = if let CanvasCommand::Embed(frame) = command {
+            let mut frame = frame.clone();
+            if let Ok(path) = frame.path.strip_prefix(&self.base_dir) {
+                // Keep the project portable.
+                frame.path = path.to_path_buf();
+            }
+
+            // Reject a broken frame (e.g., a missing file or anchor) before persisting it.
+            let embedded = EmbeddedFrame::new(frame.clone(), Point::ORIGIN);
+            let mut frames = BTreeMap::from([(frame.name.clone(), embedded)]);
+            EmbeddedFiles::new(&self.base_dir).sync(&mut frames);
+            if let Some(e) = frames.into_values().find_map(|f| f.error) {
+                return Err(orfail::Failure::new(e));
+            }
+
+            embed_command = CanvasCommand::Embed(frame);
+            &embed_command
+        } else {
+            command
+        };
         self.canvas.command(command).or_fail()?;
         for command in self
             .canvas
@@ -58,6 +129,112 @@ impl CanvasFile {
             self.writer.write_command(command).or_fail()?;
         }
         self.last_written_version = self.canvas.image().version();
+        self.embedded_files.sync(self.canvas.frames_mut());
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Frame, Ticks, METADATA_BRUSH_COLOR, METADATA_GRID};
+    use pati::{Color, ImageCommand, PatchEntry};
+
+    fn write_commands(path: &Path, commands: &[ImageCommand]) {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        let mut writer = ImageCommandWriter::new(file);
+        for command in commands {
+            writer.write_command(command).unwrap();
+        }
+    }
+
+    #[test]
+    fn broken_embedded_file() {
+        let dir = std::env::temp_dir().join(format!("patica-test-embed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let host_path = dir.join("host.pati");
+        let part_path = dir.join("part.pati");
+        let red = Color::rgb(255, 0, 0);
+        write_commands(
+            &part_path,
+            &[
+                ImageCommand::anchor("start", Some(Point::new(0, 0))),
+                ImageCommand::anchor("end", Some(Point::new(1, 1))),
+                ImageCommand::patch(vec![PatchEntry::draw(red, vec![Point::new(1, 1)])]),
+            ],
+        );
+
+        let mut host = CanvasFile::open(&host_path, true).unwrap();
+        let frame = Frame {
+            name: "part".to_owned(),
+            path: part_path.canonicalize().unwrap(),
+            top_left_anchor: "start".to_owned(),
+            bottom_right_anchor: "end".to_owned(),
+            start_ticks: Ticks::new(0),
+            end_ticks: Ticks::new(1),
+        };
+        host.command(&CanvasCommand::Embed(frame.clone())).unwrap();
+        let embedded = &host.canvas().frames()["part"];
+        assert_eq!(embedded.frame.path, Path::new("part.pati"));
+        assert_eq!(embedded.pixels.len(), 1);
+        assert!(!embedded.is_stale());
+
+        // Removing an anchor makes the frame stale but keeps its pixels.
+        write_commands(&part_path, &[ImageCommand::anchor("end", None)]);
+        host.sync().unwrap();
+        let embedded = &host.canvas().frames()["part"];
+        assert!(embedded.is_stale());
+        assert_eq!(embedded.pixels.len(), 1);
+
+        // A missing file does not prevent the host from being opened.
+        std::fs::remove_file(&part_path).unwrap();
+        let mut host = CanvasFile::open(&host_path, false).unwrap();
+        assert!(host.canvas().frames()["part"].is_stale());
+        host.command(&CanvasCommand::Draw).unwrap();
+
+        // Broken frames are rejected when they are embedded.
+        let frame = Frame {
+            name: "other".to_owned(),
+            ..frame
+        };
+        assert!(host.command(&CanvasCommand::Embed(frame)).is_err());
+        assert!(!host.canvas().frames().contains_key("other"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_metadata() {
+        let dir = std::env::temp_dir().join(format!("patica-test-metadata-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("image.pati");
+        let grid = serde_json::json!({"width": 8, "height": 8});
+        write_commands(
+            &path,
+            &[
+                ImageCommand::put(METADATA_GRID, grid),
+                ImageCommand::put(METADATA_GRID, serde_json::json!({"width": 0})),
+                ImageCommand::put(METADATA_BRUSH_COLOR, serde_json::json!("red")),
+            ],
+        );
+
+        // The malformed values are skipped while the file is loaded.
+        let mut file = CanvasFile::open(&path, false).unwrap();
+        let canvas = file.canvas();
+        assert_eq!(canvas.image().version(), Version::default() + 3);
+        assert_eq!(canvas.grid().map(|g| g.width.get()), Some(8));
+        assert_eq!(canvas.brush_color(), Color::default());
+
+        // But they are rejected as live commands.
+        let command = ImageCommand::put(METADATA_GRID, serde_json::json!(null));
+        assert!(file.command(&CanvasCommand::Image(command)).is_ok());
+        let command = ImageCommand::put(METADATA_GRID, serde_json::json!({"width": 0}));
+        assert!(file.command(&CanvasCommand::Image(command)).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct Ticks(u32);
+
+impl Ticks {
+    pub const fn new(n: u32) -> Self {
+        Self(n)
+    }
+
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    pub fn tick_delta(&mut self, delta: i32) {
+        if delta < 0 {
+            self.0 = self.0.saturating_sub(delta.unsigned_abs());
+        } else {
+            self.0 = self.0.saturating_add(delta as u32);
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.tick_delta(1);
+    }
+}
@@ -1,11 +1,72 @@
-use pati::{ImageCommand, Point};
+use crate::{frame::Frame, marker::MarkKind};
+use pati::{Color, ImageCommand, Point};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum CanvasCommand {
     Move(Point),
+
+    /// Moves the cursor to the anchor.
+    Jump(String),
+
+    /// Puts the anchor at the cursor.
+    Anchor(String),
+
+    /// Moves the camera to the cursor (the camera point is shown at the center of the screen).
+    Center,
     Scale(i8),
+    Tick(i32),
     Quit,
     Image(ImageCommand),
+
+    /// Draws the brush color at the cursor (or the marked points), or stamps the pixels being edited.
+    Draw,
+
+    /// Erases the pixel at the cursor (or the marked points).
+    Erase,
+
+    /// Sets the brush color.
+    Dip(Color),
+
+    /// Sets the brush color to the color at the cursor.
+    Pick,
+
+    /// Enters the marking mode.
+    Mark(MarkKind),
+
+    /// Enters the editing mode with the marked pixels.
+    Copy,
+
+    /// Erases the marked pixels and enters the editing mode with them.
+    Cut,
+
+    /// Enters the playing mode (the ticks advance at the FPS rate until `cancel`).
+    Play,
+
+    /// Goes back to the neutral mode.
+    Cancel,
+
+    /// Enters the editing mode with the given pixels (relative to the cursor) for placement.
+    Import(Vec<(Point, Color)>),
+    Flip(FlipDirection),
+    Rotate,
+
+    /// Embeds a region of another file as a frame whose top-left corner is at the cursor.
+    Embed(Frame),
+    Remove(RemoveTarget),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoveTarget {
+    Anchor(String),
+    Frame(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlipDirection {
+    Horizontal,
+    Vertical,
 }
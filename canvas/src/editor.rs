@@ -2,7 +2,7 @@ use crate::command::FlipDirection;
 use pati::{Color, Point};
 use std::collections::BTreeMap;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Editor {
     pixels: BTreeMap<Point, Color>,
 }
@@ -62,20 +62,9 @@ impl Editor {
     }
 
     fn center(&self) -> Point {
-        if self.pixels.is_empty() {
+        let Some((start, end)) = Point::bounding_box(self.pixels.keys().copied()) else {
             return Point::new(0, 0);
-        }
-
-        let mut start = Point::MAX;
-        let mut end = Point::MIN;
-
-        for point in self.pixels.keys().copied() {
-            start.x = start.x.min(point.x);
-            start.y = start.y.min(point.y);
-            end.x = end.x.max(point.x);
-            end.y = end.y.max(point.y);
-        }
-
+        };
         Point::new(
             (end.x - start.x) / 2 + start.x,
             (end.y - start.y) / 2 + start.y,
@@ -0,0 +1,161 @@
+use crate::clock::Ticks;
+use orfail::OrFail;
+use pati::{Color, ImageCommandReader, Point, Version, VersionedImage};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Frame {
+    pub name: String,
+
+    /// Path of the embedded file (relative paths are resolved against the directory of the host file).
+    pub path: PathBuf,
+    pub top_left_anchor: String,
+    pub bottom_right_anchor: String,
+    pub start_ticks: Ticks,
+    pub end_ticks: Ticks,
+}
+
+impl Frame {
+    pub fn is_visible(&self, ticks: Ticks) -> bool {
+        (self.start_ticks..self.end_ticks).contains(&ticks)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddedFrame {
+    pub frame: Frame,
+    pub start: Point,
+
+    // The following fields are a cache of the embedded file and are re-synced after loading.
+    #[serde(skip)]
+    pub version: Version,
+    #[serde(skip)]
+    pub pixels: BTreeMap<Point, Color>,
+
+    /// Reason why the last sync failed (the pixels of the last successful sync are kept meanwhile).
+    #[serde(skip)]
+    pub error: Option<String>,
+}
+
+impl EmbeddedFrame {
+    pub fn new(frame: Frame, start: Point) -> Self {
+        Self {
+            frame,
+            start,
+            version: Version::default(),
+            pixels: BTreeMap::new(),
+            error: None,
+        }
+    }
+
+    /// Returns `true` if the pixels may be out of date because the last sync failed.
+    pub fn is_stale(&self) -> bool {
+        self.error.is_some()
+    }
+
+    pub fn sync(&mut self, canvas: &VersionedImage) -> orfail::Result<()> {
+        let anchor = |name: &String| {
+            canvas.anchors().get(name).copied().or_fail_with(|()| {
+                format!("No such anchor in {}: {name}", self.frame.path.display())
+            })
+        };
+        let start = anchor(&self.frame.top_left_anchor).or_fail()?;
+        let end = anchor(&self.frame.bottom_right_anchor).or_fail()?;
+        self.version = canvas.version();
+        self.pixels = canvas
+            .range_pixels(start..=end)
+            .map(|(p, c)| ((p - start) + self.start, c))
+            .collect();
+        Ok(())
+    }
+}
+
+/// Follows the files embedded as frames and keeps the frames up-to-date.
+#[derive(Debug, Default)]
+pub struct EmbeddedFiles {
+    base_dir: PathBuf,
+    files: BTreeMap<PathBuf, EmbeddedFile>,
+}
+
+impl EmbeddedFiles {
+    /// Makes a new instance that resolves relative frame paths against `base_dir`
+    /// (the directory of the host file).
+    pub fn new<P: AsRef<Path>>(base_dir: P) -> Self {
+        Self {
+            base_dir: base_dir.as_ref().to_path_buf(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the commands appended to the embedded files since the last call,
+    /// and re-syncs the frames whose files have changed.
+    ///
+    /// Failures (e.g., a missing file or anchor) do not abort the sync:
+    /// the affected frames keep their last pixels and [`EmbeddedFrame::error`] is set instead.
+    /// Files that are no longer referenced by any frame are closed.
+    pub fn sync(&mut self, frames: &mut BTreeMap<String, EmbeddedFrame>) {
+        let paths = frames
+            .values()
+            .map(|f| self.base_dir.join(&f.frame.path))
+            .collect::<BTreeSet<_>>();
+        self.files.retain(|path, _| paths.contains(path));
+
+        for path in paths {
+            let is_target = |frame: &EmbeddedFrame| self.base_dir.join(&frame.frame.path) == path;
+            if let Err(e) = Self::sync_file(&mut self.files, &path) {
+                // Reopen the file next time (it may be restored).
+                self.files.remove(&path);
+                for frame in frames.values_mut().filter(|f| is_target(f)) {
+                    frame.error = Some(e.message.clone());
+                }
+                continue;
+            }
+
+            let image = &self.files[&path].image;
+            for frame in frames.values_mut().filter(|f| is_target(f)) {
+                if frame.version != image.version() || frame.is_stale() {
+                    frame.error = frame.sync(image).err().map(|e| e.message);
+                }
+            }
+        }
+    }
+
+    fn sync_file(files: &mut BTreeMap<PathBuf, EmbeddedFile>, path: &Path) -> orfail::Result<()> {
+        if !files.contains_key(path) {
+            let file = EmbeddedFile::open(path).or_fail()?;
+            files.insert(path.to_path_buf(), file);
+        }
+        let file = files.get_mut(path).or_fail()?;
+        file.sync().or_fail()
+    }
+}
+
+#[derive(Debug)]
+struct EmbeddedFile {
+    image: VersionedImage,
+    reader: ImageCommandReader<BufReader<File>>,
+}
+
+impl EmbeddedFile {
+    fn open(path: &Path) -> orfail::Result<Self> {
+        let file = File::open(path)
+            .or_fail_with(|e| format!("Failed to open file {}: {e}", path.display()))?;
+        Ok(Self {
+            image: VersionedImage::new(),
+            reader: ImageCommandReader::new(BufReader::new(file)),
+        })
+    }
+
+    fn sync(&mut self) -> orfail::Result<()> {
+        while let Some(command) = self.reader.read_command().or_fail()? {
+            self.image.apply(&command);
+        }
+        Ok(())
+    }
+}
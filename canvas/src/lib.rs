@@ -1,11 +1,22 @@
 mod canvas;
 mod canvas_agent;
 mod canvas_file;
+mod clock;
 mod command;
+mod editor;
+mod frame;
+mod marker;
 mod query;
 
-pub use canvas::Canvas;
+pub use canvas::{
+    Canvas, CanvasMode, Grid, METADATA_BACKGROUND_COLOR, METADATA_BRUSH_COLOR,
+    METADATA_FRAME_PREFIX, METADATA_GRID,
+};
 pub use canvas_agent::{CanvasAgent, CanvasAgentRequest, CanvasAgentServer};
 pub use canvas_file::CanvasFile;
-pub use command::CanvasCommand;
+pub use clock::Ticks;
+pub use command::{CanvasCommand, FlipDirection, RemoveTarget};
+pub use editor::Editor;
+pub use frame::{EmbeddedFiles, EmbeddedFrame, Frame};
+pub use marker::{MarkKind, Marker};
 pub use query::{CanvasQuery, CanvasQueryValue};
@@ -1,4 +1,4 @@
-use crate::model::Model;
+use crate::Canvas;
 use pati::{Color, Point};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
@@ -29,29 +29,42 @@ pub enum Marker {
 }
 
 impl Marker {
-    pub fn new(mark_kind: MarkKind, model: &Model) -> Self {
+    pub fn new(mark_kind: MarkKind, canvas: &Canvas) -> Self {
         match mark_kind {
-            MarkKind::Line => Self::Line(LineMarker::new(model)),
-            MarkKind::Stroke => Self::Stroke(StrokeMarker::new(model)),
-            MarkKind::Fill => Self::Fill(FillMarker::new(model)),
-            MarkKind::Rectangle => Self::Rectangle(RectangleMarker::new(model)),
-            MarkKind::Region => Self::Region(RegionMarker::new(model)),
-            MarkKind::Ellipse => Self::Ellipse(EllipseMarker::new(model)),
-            MarkKind::Color => Self::Color(ColorMarker::new(model)),
-            MarkKind::All => Self::All(AllMarker::new(model)),
+            MarkKind::Line => Self::Line(LineMarker::new(canvas)),
+            MarkKind::Stroke => Self::Stroke(StrokeMarker::new(canvas)),
+            MarkKind::Fill => Self::Fill(FillMarker::new(canvas)),
+            MarkKind::Rectangle => Self::Rectangle(RectangleMarker::new(canvas)),
+            MarkKind::Region => Self::Region(RegionMarker::new(canvas)),
+            MarkKind::Ellipse => Self::Ellipse(EllipseMarker::new(canvas)),
+            MarkKind::Color => Self::Color(ColorMarker::new(canvas)),
+            MarkKind::All => Self::All(AllMarker::new(canvas)),
         }
     }
 
-    pub fn handle_move(&mut self, model: &Model) {
+    pub fn kind(&self) -> MarkKind {
         match self {
-            Self::Line(m) => m.handle_move(model),
-            Self::Stroke(m) => m.handle_mvoe(model),
-            Self::Fill(m) => m.handle_move(model),
-            Self::Rectangle(m) => m.handle_move(model),
-            Self::Region(m) => m.handle_move(model),
-            Self::Ellipse(m) => m.handle_move(model),
-            Self::Color(m) => m.handle_move(model),
-            Self::All(m) => m.handle_move(model),
+            Self::Line(_) => MarkKind::Line,
+            Self::Stroke(_) => MarkKind::Stroke,
+            Self::Fill(_) => MarkKind::Fill,
+            Self::Rectangle(_) => MarkKind::Rectangle,
+            Self::Region(_) => MarkKind::Region,
+            Self::Ellipse(_) => MarkKind::Ellipse,
+            Self::Color(_) => MarkKind::Color,
+            Self::All(_) => MarkKind::All,
+        }
+    }
+
+    pub fn handle_move(&mut self, canvas: &Canvas) {
+        match self {
+            Self::Line(m) => m.handle_move(canvas),
+            Self::Stroke(m) => m.handle_move(canvas),
+            Self::Fill(m) => m.handle_move(canvas),
+            Self::Rectangle(m) => m.handle_move(canvas),
+            Self::Region(m) => m.handle_move(canvas),
+            Self::Ellipse(m) => m.handle_move(canvas),
+            Self::Color(m) => m.handle_move(canvas),
+            Self::All(m) => m.handle_move(canvas),
         }
     }
 
@@ -76,10 +89,10 @@ pub struct LineMarker {
 }
 
 impl LineMarker {
-    fn new(model: &Model) -> Self {
+    fn new(canvas: &Canvas) -> Self {
         Self {
-            start: model.cursor(),
-            end: model.cursor(),
+            start: canvas.cursor(),
+            end: canvas.cursor(),
         }
     }
 
@@ -87,25 +100,26 @@ impl LineMarker {
         Self { start, end }.marked_points()
     }
 
-    fn handle_move(&mut self, model: &Model) {
-        self.end = model.cursor();
+    fn handle_move(&mut self, canvas: &Canvas) {
+        self.end = canvas.cursor();
     }
 
     fn marked_points(self) -> impl Iterator<Item = Point> {
         let p0 = self.start;
         let p1 = self.end;
-        let dx = (p1.x - p0.x).abs() + 1;
-        let dy = (p1.y - p0.y).abs() + 1;
+        // Computed in `i32` as the delta between far apart points overflows `i16`.
+        let dx = (i32::from(p1.x) - i32::from(p0.x)).abs() + 1;
+        let dy = (i32::from(p1.y) - i32::from(p0.y)).abs() + 1;
         let sign_y = if p1.y > p0.y { 1 } else { -1 };
         let sign_x = if p1.x > p0.x { 1 } else { -1 };
         let (f, r, n, v0, sign0, mut v1, sign1) = if dx > dy {
-            let f = xy as fn(i16, i16) -> Point;
+            let f = xy as fn(i32, i32) -> Point;
             let r = Rational::new(dx, dy);
-            (f, r, dx, p0.x, sign_x, p0.y, sign_y)
+            (f, r, dx, i32::from(p0.x), sign_x, i32::from(p0.y), sign_y)
         } else {
-            let f = yx as fn(i16, i16) -> Point;
+            let f = yx as fn(i32, i32) -> Point;
             let r = Rational::new(dy, dx);
-            (f, r, dy, p0.y, sign_y, p0.x, sign_x)
+            (f, r, dy, i32::from(p0.y), sign_y, i32::from(p0.x), sign_x)
         };
         (0..n).map(move |i| {
             if i != 0 && (i - 1) / r != i / r {
@@ -118,30 +132,31 @@ impl LineMarker {
 
 #[derive(Debug, Clone, Copy)]
 struct Rational {
-    num: i16,
-    den: i16,
+    num: i32,
+    den: i32,
 }
 
 impl Rational {
-    const fn new(num: i16, den: i16) -> Self {
+    const fn new(num: i32, den: i32) -> Self {
         Self { num, den }
     }
 }
 
-impl std::ops::Div<Rational> for i16 {
-    type Output = i16;
+impl std::ops::Div<Rational> for i32 {
+    type Output = i32;
 
     fn div(self, rhs: Rational) -> Self::Output {
         self * rhs.den / rhs.num
     }
 }
 
-fn xy(x: i16, y: i16) -> Point {
-    Point::new(x, y)
+// The coordinates of a line always lie between its endpoints, so they fit in `i16`.
+fn xy(x: i32, y: i32) -> Point {
+    Point::new(x as i16, y as i16)
 }
 
-fn yx(y: i16, x: i16) -> Point {
-    Point::new(x, y)
+fn yx(y: i32, x: i32) -> Point {
+    Point::new(x as i16, y as i16)
 }
 
 #[derive(Debug, Clone)]
@@ -151,15 +166,15 @@ pub struct StrokeMarker {
 }
 
 impl StrokeMarker {
-    fn new(model: &Model) -> Self {
+    fn new(canvas: &Canvas) -> Self {
         Self {
-            stroke: [model.cursor()].into_iter().collect(),
-            last: model.cursor(),
+            stroke: [canvas.cursor()].into_iter().collect(),
+            last: canvas.cursor(),
         }
     }
 
-    fn handle_mvoe(&mut self, model: &Model) {
-        let cursor = model.cursor();
+    fn handle_move(&mut self, canvas: &Canvas) {
+        let cursor = canvas.cursor();
         if self.last != cursor {
             self.stroke.extend(LineMarker::line(self.last, cursor));
             self.last = cursor;
@@ -179,14 +194,11 @@ struct Region {
 
 impl Region {
     fn from_points(points: impl Iterator<Item = Point>) -> Self {
-        let mut top_left = Point::new(i16::MAX, i16::MAX);
-        let mut bottom_right = Point::new(i16::MIN, i16::MIN);
-        for point in points {
-            top_left.x = top_left.x.min(point.x);
-            top_left.y = top_left.y.min(point.y);
-            bottom_right.x = bottom_right.x.max(point.x);
-            bottom_right.y = bottom_right.y.max(point.y);
-        }
+        // No points make an empty (inverted) region.
+        let (top_left, bottom_right) = Point::bounding_box(points).unwrap_or((
+            Point::new(i16::MAX, i16::MAX),
+            Point::new(i16::MIN, i16::MIN),
+        ));
         Self {
             top_left,
             bottom_right,
@@ -240,23 +252,23 @@ pub struct FillMarker {
 }
 
 impl FillMarker {
-    fn new(model: &Model) -> Self {
+    fn new(canvas: &Canvas) -> Self {
         let mut this = Self {
-            cursor: model.cursor(),
+            cursor: canvas.cursor(),
             points: HashSet::new(),
             region: Region::from_points(
-                std::iter::once(model.cursor()).chain(model.canvas().pixels().keys().copied()),
+                std::iter::once(canvas.cursor()).chain(canvas.image().pixels().keys().copied()),
             ),
             to_be_filled: false,
         };
-        this.calc_points_to_be_filled(model);
+        this.calc_points_to_be_filled(canvas);
         this
     }
 
-    fn handle_move(&mut self, model: &Model) {
-        self.cursor = model.cursor();
-        if !self.points.contains(&model.cursor()) {
-            self.calc_points_to_be_filled(model);
+    fn handle_move(&mut self, canvas: &Canvas) {
+        self.cursor = canvas.cursor();
+        if !self.points.contains(&canvas.cursor()) {
+            self.calc_points_to_be_filled(canvas);
         }
     }
 
@@ -267,17 +279,17 @@ impl FillMarker {
             .flatten()
     }
 
-    fn calc_points_to_be_filled(&mut self, model: &Model) {
+    fn calc_points_to_be_filled(&mut self, canvas: &Canvas) {
         self.points.clear();
         self.to_be_filled = true;
 
-        let color = model.canvas().get_pixel(self.cursor);
+        let color = canvas.image().get_pixel(self.cursor);
         let mut stack = vec![self.cursor];
         while let Some(p) = stack.pop() {
             if self.points.contains(&p) {
                 continue;
             }
-            if model.canvas().get_pixel(p) != color {
+            if canvas.image().get_pixel(p) != color {
                 continue;
             }
             if !self.region.contains(p) {
@@ -301,15 +313,15 @@ pub struct RectangleMarker {
 }
 
 impl RectangleMarker {
-    fn new(model: &Model) -> Self {
+    fn new(canvas: &Canvas) -> Self {
         Self {
-            start: model.cursor(),
-            end: model.cursor(),
+            start: canvas.cursor(),
+            end: canvas.cursor(),
         }
     }
 
-    fn handle_move(&mut self, model: &Model) {
-        self.end = model.cursor();
+    fn handle_move(&mut self, canvas: &Canvas) {
+        self.end = canvas.cursor();
     }
 
     fn marked_points(&self) -> impl Iterator<Item = Point> {
@@ -323,14 +335,14 @@ pub struct RegionMarker {
 }
 
 impl RegionMarker {
-    fn new(model: &Model) -> Self {
+    fn new(canvas: &Canvas) -> Self {
         Self {
-            inner: RectangleMarker::new(model),
+            inner: RectangleMarker::new(canvas),
         }
     }
 
-    fn handle_move(&mut self, model: &Model) {
-        self.inner.handle_move(model);
+    fn handle_move(&mut self, canvas: &Canvas) {
+        self.inner.handle_move(canvas);
     }
 
     fn marked_points(&self) -> impl Iterator<Item = Point> {
@@ -346,17 +358,17 @@ pub struct EllipseMarker {
 }
 
 impl EllipseMarker {
-    fn new(model: &Model) -> Self {
+    fn new(canvas: &Canvas) -> Self {
         Self {
-            start: model.cursor(),
-            cursor: model.cursor(),
-            points: vec![model.cursor()].into_iter().collect(),
+            start: canvas.cursor(),
+            cursor: canvas.cursor(),
+            points: vec![canvas.cursor()].into_iter().collect(),
         }
     }
 
-    fn handle_move(&mut self, model: &Model) {
-        if self.cursor != model.cursor() {
-            self.cursor = model.cursor();
+    fn handle_move(&mut self, canvas: &Canvas) {
+        if self.cursor != canvas.cursor() {
+            self.cursor = canvas.cursor();
             self.calc_points();
         }
     }
@@ -422,21 +434,21 @@ pub struct ColorMarker {
 }
 
 impl ColorMarker {
-    fn new(model: &Model) -> Self {
-        let color = model.canvas().get_pixel(model.cursor());
+    fn new(canvas: &Canvas) -> Self {
+        let color = canvas.image().get_pixel(canvas.cursor());
         let mut this = Self {
             color,
             points: HashSet::new(),
         };
-        this.calc_points(model);
+        this.calc_points(canvas);
         this
     }
 
-    fn handle_move(&mut self, model: &Model) {
-        let color = model.canvas().get_pixel(model.cursor());
+    fn handle_move(&mut self, canvas: &Canvas) {
+        let color = canvas.image().get_pixel(canvas.cursor());
         if self.color != color {
             self.color = color;
-            self.calc_points(model);
+            self.calc_points(canvas);
         }
     }
 
@@ -444,13 +456,13 @@ impl ColorMarker {
         self.points.iter().copied()
     }
 
-    fn calc_points(&mut self, model: &Model) {
+    fn calc_points(&mut self, canvas: &Canvas) {
         self.points.clear();
         let Some(color) = self.color else {
             return;
         };
-        self.points = model
-            .canvas()
+        self.points = canvas
+            .image()
             .pixels()
             .iter()
             .filter(|p| *p.1 == color)
@@ -465,17 +477,34 @@ pub struct AllMarker {
 }
 
 impl AllMarker {
-    fn new(model: &Model) -> Self {
+    fn new(canvas: &Canvas) -> Self {
         Self {
-            points: model.canvas().pixels().keys().copied().collect(),
+            points: canvas.image().pixels().keys().copied().collect(),
         }
     }
 
-    fn handle_move(&mut self, model: &Model) {
-        self.points = model.canvas().pixels().keys().copied().collect();
+    fn handle_move(&mut self, canvas: &Canvas) {
+        self.points = canvas.image().pixels().keys().copied().collect();
     }
 
     fn marked_points(&self) -> impl '_ + Iterator<Item = Point> {
         self.points.iter().copied()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn far_apart_line() {
+        let start = Point::new(i16::MIN, -1);
+        let end = Point::new(i16::MAX, 1);
+        let points = LineMarker::line(start, end).collect::<Vec<_>>();
+        assert_eq!(points.len(), 1 << 16);
+        assert_eq!(points.first(), Some(&start));
+        assert_eq!(points.last(), Some(&end));
+        assert!(points.windows(2).all(|w| w[1].x == w[0].x + 1));
+        assert!(points.iter().all(|p| (-1..=1).contains(&p.y)));
+    }
+}
@@ -1,6 +1,6 @@
-use pati::{Color, Point};
+use pati::{Color, Point, Version};
 use serde::{Deserialize, Serialize};
-use std::num::NonZeroU8;
+use std::{collections::BTreeMap, num::NonZeroU8};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -11,6 +11,13 @@ pub enum CanvasQuery {
     BackgroundColor,
     Scale,
     Fps,
+    Anchors,
+    MetadataKeys,
+    Metadata(String),
+    Pixel(Point),
+    Bounds,
+    Version,
+    ColorHistogram,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -22,4 +29,13 @@ pub enum CanvasQueryValue {
     BackgroundColor(Color),
     Scale(NonZeroU8),
     Fps(NonZeroU8),
+    Anchors(BTreeMap<String, Point>),
+    MetadataKeys(Vec<String>),
+    Metadata(Option<serde_json::Value>),
+    Pixel(Option<Color>),
+    Bounds(Option<(Point, Point)>),
+    Version(Version),
+
+    /// Pairs of a color and the number of pixels with that color, in descending order of the count.
+    ColorHistogram(Vec<(Color, usize)>),
 }
@@ -51,6 +51,13 @@ impl VersionedImage {
         self.image.metadata()
     }
 
+    /// Gets the smallest rectangle (inclusive) that contains all pixels in this image.
+    ///
+    /// Returns `None` if this image has no pixels.
+    pub fn bounding_box(&self) -> Option<(Point, Point)> {
+        self.image.bounding_box()
+    }
+
     /// Applies the given command to this image.
     ///
     /// Returns `true` if the image is changed, otherwise `false`.
@@ -70,6 +77,13 @@ impl VersionedImage {
         &self.log.commands()[i..]
     }
 
+    /// Restores the image at the given version.
+    ///
+    /// Returns `None` if the version is newer than the current one.
+    pub fn restore_image(&self, version: Version) -> Option<Image> {
+        self.log.restore_image(version)
+    }
+
     /// Calculates the diff between the current image and the image at the given version.
     pub fn diff(&self, version: Version) -> Option<PatchImageCommand> {
         let image = self.log.restore_image(version)?;
@@ -119,6 +133,13 @@ impl Image {
         &self.metadata
     }
 
+    /// Gets the smallest rectangle (inclusive) that contains all pixels in this image.
+    ///
+    /// Returns `None` if this image has no pixels.
+    pub fn bounding_box(&self) -> Option<(Point, Point)> {
+        Point::bounding_box(self.pixels.keys().copied())
+    }
+
     /// Applies the given command to this image.
     ///
     /// Returns `true` if the image is changed, otherwise `false`.
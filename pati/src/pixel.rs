@@ -86,6 +86,20 @@ impl Point {
     pub const fn new(x: i16, y: i16) -> Self {
         Self { x, y }
     }
+
+    /// Gets the smallest rectangle (inclusive) that contains all the given points.
+    ///
+    /// Returns `None` if `points` is empty.
+    pub fn bounding_box(points: impl IntoIterator<Item = Self>) -> Option<(Self, Self)> {
+        points.into_iter().fold(None, |acc, p| {
+            let (mut start, mut end) = acc.unwrap_or((p, p));
+            start.x = start.x.min(p.x);
+            start.y = start.y.min(p.y);
+            end.x = end.x.max(p.x);
+            end.y = end.y.max(p.y);
+            Some((start, end))
+        })
+    }
 }
 
 impl std::ops::Add for Point {
@@ -0,0 +1,182 @@
+use orfail::OrFail;
+use pati::{Color, Image, Point};
+use std::io::Write;
+
+const ASCII_RAMP: &[u8] = b"@%#*+=-:. ";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorMode {
+    /// 24-bit colors.
+    #[default]
+    #[clap(name = "truecolor")]
+    TrueColor,
+
+    /// xterm 256-color palette.
+    #[clap(name = "256")]
+    Ansi256,
+
+    /// Plain ASCII characters without escape sequences.
+    Ascii,
+}
+
+/// Writes the pixels of the given region (inclusive) as terminal text.
+///
+/// Each text cell represents two vertically adjacent pixels.
+/// Missing or fully transparent pixels are filled with `background_color` if given,
+/// otherwise they are left as the terminal's default background.
+pub fn write_image<W: Write>(
+    mut writer: W,
+    image: &Image,
+    (start, end): (Point, Point),
+    background_color: Option<Color>,
+    mode: ColorMode,
+) -> orfail::Result<()> {
+    let get = |x: i16, y: i16| {
+        image
+            .get_pixel(Point::new(x, y))
+            .filter(|c| c.a != 0)
+            .or(background_color)
+    };
+
+    for y in (start.y..=end.y).step_by(2) {
+        for x in start.x..=end.x {
+            let upper = get(x, y);
+            let lower = if y < end.y { get(x, y + 1) } else { None };
+            write_cell(&mut writer, upper, lower, mode).or_fail()?;
+        }
+        if mode != ColorMode::Ascii {
+            write!(writer, "\x1b[0m").or_fail()?;
+        }
+        writeln!(writer).or_fail()?;
+    }
+    Ok(())
+}
+
+fn write_cell<W: Write>(
+    writer: &mut W,
+    upper: Option<Color>,
+    lower: Option<Color>,
+    mode: ColorMode,
+) -> std::io::Result<()> {
+    if mode == ColorMode::Ascii {
+        let c = match (upper, lower) {
+            (None, None) => b' ',
+            (Some(c), None) | (None, Some(c)) => ascii_char((luminance(c) + 255) / 2),
+            (Some(c0), Some(c1)) => ascii_char((luminance(c0) + luminance(c1)) / 2),
+        };
+        return write!(writer, "{}", c as char);
+    }
+
+    match (upper, lower) {
+        (None, None) => write!(writer, "\x1b[0m "),
+        (Some(c), None) => write!(writer, "\x1b[0m{}\u{2580}", fg(c, mode)),
+        (None, Some(c)) => write!(writer, "\x1b[0m{}\u{2584}", fg(c, mode)),
+        (Some(c0), Some(c1)) => write!(writer, "{}{}\u{2580}", fg(c0, mode), bg(c1, mode)),
+    }
+}
+
+fn fg(c: Color, mode: ColorMode) -> String {
+    if mode == ColorMode::TrueColor {
+        format!("\x1b[38;2;{};{};{}m", c.r, c.g, c.b)
+    } else {
+        format!("\x1b[38;5;{}m", to_ansi256(c))
+    }
+}
+
+fn bg(c: Color, mode: ColorMode) -> String {
+    if mode == ColorMode::TrueColor {
+        format!("\x1b[48;2;{};{};{}m", c.r, c.g, c.b)
+    } else {
+        format!("\x1b[48;5;{}m", to_ansi256(c))
+    }
+}
+
+fn to_ansi256(c: Color) -> u8 {
+    if c.r == c.g && c.g == c.b {
+        // Use the grayscale ramp (232..=255) except for the darkest and lightest values.
+        return match c.r {
+            0..=7 => 16,
+            249..=255 => 231,
+            v => 232 + ((v as u16 - 8) * 24 / 241) as u8,
+        };
+    }
+    let level = |v: u8| ((v as u16 * 5 + 127) / 255) as u8;
+    16 + 36 * level(c.r) + 6 * level(c.g) + level(c.b)
+}
+
+fn luminance(c: Color) -> u32 {
+    (c.r as u32 * 299 + c.g as u32 * 587 + c.b as u32 * 114) / 1000
+}
+
+fn ascii_char(luminance: u32) -> u8 {
+    ASCII_RAMP[(luminance as usize * (ASCII_RAMP.len() - 1)) / 255]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pati::ImageCommand;
+
+    fn render(
+        pixels: &[(i16, i16, Color)],
+        region: (Point, Point),
+        background_color: Option<Color>,
+        mode: ColorMode,
+    ) -> String {
+        let mut image = Image::new();
+        image.apply(&ImageCommand::draw_pixels(
+            pixels.iter().map(|&(x, y, c)| (Point::new(x, y), c)),
+        ));
+        let mut buf = Vec::new();
+        write_image(&mut buf, &image, region, background_color, mode).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn half_block_cells() {
+        let (red, blue) = (Color::rgb(255, 0, 0), Color::rgb(0, 0, 255));
+        let pixels = [(0, 0, red), (0, 1, blue), (0, 2, blue), (1, 1, red)];
+        let region = (Point::new(0, 0), Point::new(1, 2));
+
+        // The last row of an odd-height region only has upper halves.
+        assert_eq!(
+            render(&pixels, region, None, ColorMode::TrueColor),
+            [
+                "\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m\u{2580}",
+                "\x1b[0m\x1b[38;2;255;0;0m\u{2584}\x1b[0m\n",
+                "\x1b[0m\x1b[38;2;0;0;255m\u{2580}\x1b[0m \x1b[0m\n",
+            ]
+            .concat()
+        );
+
+        // A cell with an empty half is lighter.
+        assert_eq!(render(&pixels, region, None, ColorMode::Ascii), "%=\n= \n");
+    }
+
+    #[test]
+    fn transparent_pixels() {
+        let clear = Color::rgba(255, 0, 0, 0);
+        let region = (Point::new(0, 0), Point::new(0, 1));
+        assert_eq!(
+            render(&[(0, 0, clear)], region, None, ColorMode::TrueColor),
+            "\x1b[0m \x1b[0m\n"
+        );
+
+        // Transparent and missing pixels are filled with the background color.
+        let gray = Color::rgb(128, 128, 128);
+        assert_eq!(
+            render(&[(0, 0, clear)], region, Some(gray), ColorMode::Ansi256),
+            "\x1b[38;5;243m\x1b[48;5;243m\u{2580}\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn ansi256_quantization() {
+        assert_eq!(to_ansi256(Color::rgb(255, 0, 0)), 196);
+        assert_eq!(to_ansi256(Color::rgb(0, 0, 255)), 21);
+        assert_eq!(to_ansi256(Color::rgb(255, 135, 0)), 214);
+        assert_eq!(to_ansi256(Color::rgb(0, 0, 0)), 16);
+        assert_eq!(to_ansi256(Color::rgb(255, 255, 255)), 231);
+        assert_eq!(to_ansi256(Color::rgb(128, 128, 128)), 243);
+    }
+}
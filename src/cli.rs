@@ -1,9 +1,36 @@
-use crate::{game::Game, model::Model};
+use crate::{
+    ansi::ColorMode,
+    config::Config,
+    game::Game,
+    history::{color_to_hex, CommandKind, ImageDiff, LogEntry, Merge},
+    model::Model,
+    palette::{Palette, PaletteFormat, ANCHOR_PALETTE_END, ANCHOR_PALETTE_START},
+    palette_panel::PalettePanel,
+    screen::Screen,
+    template::Template,
+    timelapse::{Sampling, Timelapse},
+};
 use orfail::OrFail;
-use pagurus::Game as _;
+use pagurus::{
+    image::Canvas as PagurusCanvas,
+    spatial::Size,
+    video::{PixelFormat, VideoFrame, VideoFrameSpec},
+    Game as _,
+};
 use pagurus_tui::{TuiSystem, TuiSystemOptions};
-use paticanvas::{CanvasAgentRequest, CanvasAgentServer, CanvasFile};
-use std::path::PathBuf;
+use pati::{Color, ImageCommandReader, ImageCommandWriter, Point, Version};
+use paticanvas::{
+    Canvas, CanvasAgent, CanvasAgentRequest, CanvasAgentServer, CanvasCommand, CanvasFile,
+    CanvasQuery, CanvasQueryValue, EmbeddedFiles, FlipDirection, Frame, Ticks,
+    METADATA_BACKGROUND_COLOR,
+};
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, BufWriter, Write},
+    num::{NonZeroU8, NonZeroUsize},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 const ENV_PATICA_PORT: &str = "PATICA_PORT";
 
@@ -28,47 +55,158 @@ const ENV_PATICA_PORT: &str = "PATICA_PORT";
 #[derive(Debug, clap::Parser)]
 #[clap(version, about)]
 pub enum Args {
+    New(NewCommand),
     Open(OpenCommand),
-    // Apply(ApplyCommand), // TODO: Rename to Command
-    // Include(IncludeCommand),
-    // Embed(EmbedCommand),
-    // Export(ExportCommand),
-    // #[clap(subcommand)]
-    // Get(GetCommand), // TODO: Rename to Query
+    Command(CommandCommand),
+    Apply(ApplyCommand),
+    Include(IncludeCommand),
+    Embed(EmbedCommand),
+    Export(ExportCommand),
+    Show(ShowCommand),
+    #[clap(subcommand)]
+    Palette(PaletteCommand),
+    Timelapse(TimelapseCommand),
+    Render(RenderCommand),
+    Query(QueryCommand),
+    Log(LogCommand),
+    Checkout(CheckoutCommand),
+    Diff(DiffCommand),
+    GitTextconv(GitTextconvCommand),
+    GitMergeDriver(GitMergeDriverCommand),
+    GitSetup(GitSetupCommand),
+    Watch(WatchCommand),
 }
 
 impl Args {
     pub fn run(&self) -> orfail::Result<()> {
         match self {
+            Self::New(cmd) => cmd.run().or_fail(),
             Self::Open(cmd) => cmd.run().or_fail().inspect_err(|_| {
                 // This is needed to leave the raw terminal mode before printing the error.
                 println!();
             }),
-            // Self::Apply(cmd) => cmd.run().or_fail(),
-            // Self::Include(cmd) => cmd.run().or_fail(),
-            // Self::Embed(cmd) => cmd.run().or_fail(),
-            // Self::Export(cmd) => cmd.run().or_fail(),
-            // Self::Get(cmd) => cmd.run().or_fail(),
+            Self::Command(cmd) => cmd.run().or_fail(),
+            Self::Apply(cmd) => cmd.run().or_fail(),
+            Self::Include(cmd) => cmd.run().or_fail(),
+            Self::Embed(cmd) => cmd.run().or_fail(),
+            Self::Export(cmd) => cmd.run().or_fail(),
+            Self::Show(cmd) => cmd.run().or_fail(),
+            Self::Palette(cmd) => cmd.run().or_fail(),
+            Self::Timelapse(cmd) => cmd.run().or_fail(),
+            Self::Render(cmd) => cmd.run().or_fail(),
+            Self::Query(cmd) => cmd.run().or_fail(),
+            Self::Log(cmd) => cmd.run().or_fail(),
+            Self::Checkout(cmd) => cmd.run().or_fail(),
+            Self::Diff(cmd) => cmd.run().or_fail(),
+            Self::GitTextconv(cmd) => cmd.run().or_fail(),
+            Self::GitMergeDriver(cmd) => cmd.run().or_fail(),
+            Self::GitSetup(cmd) => cmd.run().or_fail(),
+            Self::Watch(cmd) => cmd.run().or_fail(),
         }
     }
 }
 
+/// Creates a new file from a template.
+#[derive(Debug, clap::Args)]
+pub struct NewCommand {
+    path: PathBuf,
+
+    /// Template name (user templates are placed in `~/.config/patica/templates/<NAME>.json`).
+    #[clap(short, long, default_value = "default")]
+    template: String,
+
+    /// Template file to use instead of a named template.
+    #[clap(long)]
+    template_file: Option<PathBuf>,
+
+    /// Config file overriding the user and project configs.
+    #[clap(long)]
+    config: Option<PathBuf>,
+}
+
+impl NewCommand {
+    fn run(&self) -> orfail::Result<()> {
+        (!self.path.exists())
+            .or_fail_with(|()| format!("File already exists: {}", self.path.display()))?;
+        let template = if let Some(path) = &self.template_file {
+            Template::load_file(path).or_fail()?
+        } else {
+            Template::load(&self.template).or_fail()?
+        };
+        let commands = template.to_image_commands().or_fail()?;
+        let on_create = Config::load(&self.path, self.config.as_deref())
+            .or_fail()?
+            .on_create;
+
+        let mut canvas_file = CanvasFile::open(&self.path, true).or_fail()?;
+        for command in commands {
+            canvas_file
+                .command(&CanvasCommand::Image(command))
+                .or_fail()?;
+        }
+        if let Some(command) = on_create {
+            canvas_file.command(&command).or_fail()?;
+        }
+        println!("Created {}", self.path.display());
+        Ok(())
+    }
+}
+
 #[derive(Debug, clap::Args)]
 pub struct OpenCommand {
     path: PathBuf,
-    // TODO: --config
+
+    /// Config file overriding the user and project configs.
+    ///
+    /// Configs are merged in the following order:
+    /// built-in defaults, `~/.config/patica.json`,
+    /// `.patica.json` in the nearest ancestor directory of `PATH`, and this file.
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// Palette file (`.gpl`, `.pal`, `.hex` or `.pati`) shown in the palette panel.
+    ///
+    /// This overrides `palette.path` in the config.
+    /// If neither is specified, the region between the `palette.start` and `palette.end`
+    /// anchors of the canvas is used as the palette.
+    #[clap(long)]
+    palette: Option<PathBuf>,
 }
 
 impl OpenCommand {
     fn run(&self) -> orfail::Result<()> {
-        let canvas_file = CanvasFile::open(&self.path, true).or_fail()?;
+        let config = Config::load(&self.path, self.config.as_deref()).or_fail()?;
+        let mut canvas_file = CanvasFile::open(&self.path, true).or_fail()?;
+        if let Some(command) = &config.on_open {
+            canvas_file.command(command).or_fail()?;
+        }
+        let palette_path = self.palette.as_ref().or(config.palette.path.as_ref());
+        let palette = if let Some(path) = palette_path {
+            Some(Palette::load(path).or_fail()?)
+        } else {
+            let anchors = canvas_file.canvas().image().anchors();
+            if anchors.contains_key(ANCHOR_PALETTE_START)
+                && anchors.contains_key(ANCHOR_PALETTE_END)
+            {
+                let image = current_image(canvas_file.canvas()).or_fail()?;
+                Some(Palette::from_image(&image).or_fail()?)
+            } else {
+                None
+            }
+        };
+        let palette_panel = palette.map(|p| PalettePanel::new(&p, &config.palette));
+
         let mut game = Game::new(Model::new(canvas_file));
+        game.set_config(config);
+        if let Some(palette_panel) = palette_panel {
+            game.set_palette_panel(palette_panel);
+        }
 
         let mut agent_server = CanvasAgentServer::start().or_fail()?;
         std::env::set_var(ENV_PATICA_PORT, agent_server.port().to_string());
 
         let options = TuiSystemOptions {
-            disable_mouse: true,
+            disable_mouse: false,
             disable_alternate_screen: false,
         };
         let mut system = TuiSystem::with_options(options).or_fail()?;
@@ -95,8 +233,8 @@ impl OpenCommand {
         };
         match request {
             CanvasAgentRequest::Command(command) => {
-                game.model_mut().command(&command).or_fail()?;
-                server.send_response(from, ()).or_fail()?;
+                let result = game.model_mut().command(&command);
+                server.send_response(from, result).or_fail()?;
             }
             CanvasAgentRequest::Query(query) => {
                 let value = game.model().query(&query);
@@ -107,263 +245,1486 @@ impl OpenCommand {
     }
 }
 
-// #[derive(Debug)]
-// struct EmbeddedCanvas {
-//     path: PathBuf,
-//     canvas: VersionedImage,
-//     reader: ImageCommandReader<BufReader<std::fs::File>>,
-// }
-
-// impl EmbeddedCanvas {
-//     fn new(path: &PathBuf) -> orfail::Result<Self> {
-//         let file = std::fs::File::open(path).or_fail()?;
-//         let reader = ImageCommandReader::new(BufReader::new(file));
-//         let canvas = VersionedImage::default(); // TODO: use Canvas
-//         Ok(Self {
-//             path: path.clone(),
-//             canvas,
-//             reader,
-//         })
-//     }
-
-//     fn sync(&mut self, model: &mut Model) -> orfail::Result<bool> {
-//         while let Some(command) = self.reader.read_command().or_fail()? {
-//             self.canvas.apply(&command).or_fail()?;
-//         }
-//         for embedded in model.frames_mut().values_mut() {
-//             if embedded.frame.path != self.path {
-//                 continue;
-//             }
-//             if self.canvas.version() == embedded.version {
-//                 continue;
-//             }
-//             embedded.sync(&self.canvas).or_fail()?;
-//         }
-//         Ok(model.frames().values().any(|f| f.frame.path == self.path))
-//     }
-// }
-
-// #[derive(Debug, clap::Args)]
-// pub struct ApplyCommand {
-//     #[clap(short, long, default_value_t = 7539)]
-//     port: u16,
-//     // TODO: path
-// }
-
-// impl ApplyCommand {
-//     fn run(&self) -> orfail::Result<()> {
-//         let commands: Vec<Command> =
-//             serde_json::from_reader(&mut std::io::stdin().lock()).or_fail()?;
-//         apply_commands(self.port, &commands).or_fail()?;
-//         Ok(())
-//     }
-// }
-
-// #[derive(Debug, clap::Args)]
-// pub struct IncludeCommand {
-//     #[clap(short, long, default_value_t = 7539)]
-//     port: u16,
-
-//     #[clap(long)]
-//     start_anchor: Option<String>,
-
-//     #[clap(long)]
-//     end_anchor: Option<String>,
-
-//     include_file: PathBuf,
-// }
-
-// impl IncludeCommand {
-//     fn run(&self) -> orfail::Result<()> {
-//         let canvas = load_canvas(&self.include_file).or_fail()?;
-//         let mut start = Point::new(i16::MIN, i16::MIN);
-//         let mut end = Point::new(i16::MAX, i16::MAX);
-//         if let Some(anchor) = &self.start_anchor {
-//             start = canvas
-//                 .anchors()
-//                 .get(anchor)
-//                 .copied()
-//                 .or_fail_with(|()| format!("No such anchor: {anchor}"))?;
-//         }
-//         if let Some(anchor) = &self.end_anchor {
-//             end = canvas
-//                 .anchors()
-//                 .get(anchor)
-//                 .copied()
-//                 .or_fail_with(|()| format!("No such anchor: {anchor}"))?;
-//         }
-//         (start.x <= end.x && start.y <= end.y).or_fail_with(|()| {
-//             format!(
-//                 "Empty range: start=[{},{}]({}), end=[{},{}]({})",
-//                 start.x,
-//                 start.y,
-//                 self.start_anchor.as_ref().expect("unreachable"),
-//                 end.x,
-//                 end.y,
-//                 self.end_anchor.as_ref().expect("unreachable"),
-//             )
-//         })?;
-//         let origin = Point::new(
-//             ((end.x as i32 - start.x as i32 + 1) / 2 + start.x as i32) as i16,
-//             ((end.y as i32 - start.y as i32 + 1) / 2 + start.y as i32) as i16,
-//         );
-
-//         let mut pixels = Vec::new();
-//         for (point, color) in canvas.range_pixels(start..=end) {
-//             pixels.push((point - origin, color));
-//         }
-//         let command = Command::Import(pixels);
-//         apply_commands(self.port, &[command]).or_fail()?;
-//         Ok(())
-//     }
-// }
-
-// #[derive(Debug, clap::Args)]
-// pub struct EmbedCommand {
-//     #[clap(short, long, default_value_t = 7539)]
-//     port: u16,
-
-//     #[clap(long = "top-left")]
-//     top_left_anchor: String,
-
-//     #[clap(long = "bottom-right")]
-//     bottom_right_anchor: String,
-
-//     #[clap(long = "time", default_value_t = 0)]
-//     start_ticks: u32,
-
-//     #[clap(long = "duration", default_value_t = 1)]
-//     duration_ticks: u32,
-
-//     #[clap(long)]
-//     name: String,
-
-//     path: PathBuf,
-//     // TODO: nosync
-// }
-
-// impl EmbedCommand {
-//     fn run(&self) -> orfail::Result<()> {
-//         let frame = Frame {
-//             name: self.name.clone(),
-//             path: self.path.clone(),
-//             top_left_anchor: self.top_left_anchor.clone(),
-//             bottom_right_anchor: self.bottom_right_anchor.clone(),
-//             start_ticks: Ticks::new(self.start_ticks),
-//             end_ticks: Ticks::new(self.start_ticks + self.duration_ticks),
-//         };
-//         let command = Command::Embed(frame);
-//         apply_commands(self.port, &[command]).or_fail()?;
-//         Ok(())
-//     }
-// }
-
-// fn apply_commands(port: u16, commands: &[Command]) -> orfail::Result<()> {
-//     let mut client = RemoteCommandClient::connect(port).or_fail()?;
-//     client.send_commands(commands).or_fail()?;
-//     Ok(())
-// }
-
-// #[derive(Debug, clap::Args)]
-// pub struct ExportCommand {
-//     path: PathBuf,
-
-//     #[clap(short, long)]
-//     output: Option<PathBuf>,
-// }
-
-// impl ExportCommand {
-//     fn run(&self) -> orfail::Result<()> {
-//         let output = self
-//             .output
-//             .clone()
-//             .unwrap_or_else(|| self.path.with_extension("bmp"));
-//         let canvas = load_canvas(&self.path).or_fail()?;
-
-//         let mut start = Point::new(0, 0);
-//         let mut end = Point::new(0, 0);
-//         for point in canvas.pixels().keys().copied() {
-//             start.y = start.y.min(point.y);
-//             start.x = start.x.min(point.x);
-//             end.y = end.y.max(point.y);
-//             end.x = end.x.max(point.x);
-//         }
-//         crate::bmp::write_image(
-//             BufWriter::new(std::fs::File::create(&output).or_fail()?),
-//             (end.x - start.x + 1) as u16,
-//             (end.y - start.y + 1) as u16,
-//             canvas
-//                 .pixels()
-//                 .iter()
-//                 .map(|(&point, &color)| (point - start, color)),
-//         )
-//         .or_fail()?;
-//         println!("Exported to {}", output.display());
-//         Ok(())
-//     }
-// }
-
-// fn load_canvas<P: AsRef<Path>>(path: &P) -> orfail::Result<pati::Image> {
-//     let file = std::fs::File::open(path).or_fail()?;
-//     let mut reader = ImageCommandReader::new(BufReader::new(file));
-//     let mut canvas = pati::Image::new();
-//     while let Some(command) = reader.read_command().or_fail()? {
-//         canvas.apply(&command);
-//     }
-//     Ok(canvas)
-// }
-
-// #[derive(Debug, clap::Subcommand)]
-// pub enum GetCommand {
-//     // TODO: port arg
-//     BackgroundColor { path: PathBuf },
-//     BrushColor { path: PathBuf },
-//     Anchors { path: PathBuf },
-// }
-
-// impl GetCommand {
-//     fn run(&self) -> orfail::Result<()> {
-//         match self {
-//             GetCommand::BackgroundColor { .. } => {
-//                 let model = self.load_model().or_fail()?;
-//                 self.output(model.background_color()).or_fail()?;
-//             }
-//             GetCommand::BrushColor { .. } => {
-//                 let model = self.load_model().or_fail()?;
-//                 self.output(model.brush_color()).or_fail()?;
-//             }
-//             GetCommand::Anchors { .. } => {
-//                 let model = self.load_model().or_fail()?;
-//                 self.output(model.canvas().anchors()).or_fail()?;
-//             }
-//         }
-//         Ok(())
-//     }
-
-//     fn load_model(&self) -> orfail::Result<Model> {
-//         let file = std::fs::File::open(self.path()).or_fail()?;
-//         let mut reader = ImageCommandReader::new(BufReader::new(file));
-//         let mut model = Model::default();
-//         while let Some(command) = reader.read_command().or_fail()? {
-//             model.canvas_mut().apply(&command);
-//         }
-//         model.initialize().or_fail()?;
-//         Ok(model)
-//     }
-
-//     fn output(&self, value: impl serde::Serialize) -> orfail::Result<()> {
-//         let stdout = std::io::stdout();
-//         let mut stdout = stdout.lock();
-//         serde_json::to_writer(&mut stdout, &value).or_fail()?;
-//         writeln!(&mut stdout).or_fail()?;
-//         Ok(())
-//     }
-
-//     fn path(&self) -> &PathBuf {
-//         match self {
-//             GetCommand::BackgroundColor { path } => path,
-//             GetCommand::BrushColor { path } => path,
-//             GetCommand::Anchors { path } => path,
-//         }
-//     }
-// }
+#[derive(Debug, clap::Args)]
+pub struct CommandCommand {
+    /// Port number of the editor (defaults to `$PATICA_PORT`).
+    #[clap(short, long)]
+    port: Option<u16>,
+
+    /// JSON commands to send (if omitted, commands are read from the standard input).
+    commands: Vec<String>,
+}
+
+impl CommandCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let commands = if self.commands.is_empty() {
+            serde_json::Deserializer::from_reader(std::io::stdin().lock())
+                .into_iter::<CanvasCommand>()
+                .collect::<Result<Vec<_>, _>>()
+                .or_fail()?
+        } else {
+            self.commands
+                .iter()
+                .map(|c| serde_json::from_str(c))
+                .collect::<Result<Vec<_>, _>>()
+                .or_fail()?
+        };
+
+        let mut agent = connect_agent(self.port).or_fail()?;
+        let mut failed = 0;
+        for command in commands {
+            let result = agent.command(command).or_fail()?;
+            let report = match result {
+                Ok(()) => serde_json::json!({"ok": true}),
+                Err(e) => {
+                    failed += 1;
+                    serde_json::json!({"ok": false, "error": e.message})
+                }
+            };
+            println!("{report}");
+        }
+        (failed == 0).or_fail_with(|()| format!("{failed} command(s) failed"))?;
+        Ok(())
+    }
+}
+
+/// Applies a script of canvas commands to a file without opening the editor.
+///
+/// The script is a stream of JSON commands, the same as those accepted by `patica command`
+/// (e.g., `{"move":[1,0]}`, `{"mark":"rectangle"}`, `"draw"`).
+/// All commands are checked before anything is written,
+/// so a failing command leaves the file untouched.
+#[derive(Debug, clap::Args)]
+pub struct ApplyCommand {
+    path: PathBuf,
+
+    /// Script file (`-` means the standard input).
+    script: PathBuf,
+
+    /// Prints the resulting differences instead of updating the file.
+    #[clap(short = 'n', long)]
+    dry_run: bool,
+
+    /// Prints the differences as JSON (only with `--dry-run`).
+    #[clap(long, requires = "dry_run")]
+    json: bool,
+}
+
+impl ApplyCommand {
+    fn run(&self) -> orfail::Result<()> {
+        self.apply(std::io::stdout().lock()).or_fail()
+    }
+
+    /// Applies the script, or writes the resulting differences to `writer` if `--dry-run` is given.
+    fn apply<W: Write>(&self, mut writer: W) -> orfail::Result<()> {
+        let commands = self.read_script().or_fail()?;
+
+        // Validate the script against a copy of the canvas so that nothing is written on failure.
+        let mut file = CanvasFile::open(&self.path, false).or_fail()?;
+        let version = file.canvas().image().version();
+        let mut canvas = file.canvas().clone();
+        let old = current_image(&canvas).or_fail()?;
+        for (i, command) in commands.iter().enumerate() {
+            canvas
+                .command(command)
+                .or_fail_with(|e| format!("Command #{} failed: {e}", i + 1))?;
+        }
+
+        if self.dry_run {
+            let new = current_image(&canvas).or_fail()?;
+            let diff = ImageDiff::new(&old, &new);
+            if self.json {
+                serde_json::to_writer(&mut writer, &diff).or_fail()?;
+                writeln!(writer).or_fail()?;
+            } else {
+                write_diff(&mut writer, &diff).or_fail()?;
+            }
+            return Ok(());
+        }
+
+        file.commands_at(version, &commands).or_fail()?;
+        eprintln!("Applied {} command(s)", commands.len());
+        Ok(())
+    }
+
+    fn read_script(&self) -> orfail::Result<Vec<CanvasCommand>> {
+        let reader: Box<dyn std::io::Read> = if self.script.as_os_str() == "-" {
+            Box::new(std::io::stdin().lock())
+        } else {
+            let file = std::fs::File::open(&self.script)
+                .or_fail_with(|e| format!("Failed to open file {}: {e}", self.script.display()))?;
+            Box::new(BufReader::new(file))
+        };
+        serde_json::Deserializer::from_reader(reader)
+            .into_iter::<CanvasCommand>()
+            .collect::<Result<Vec<_>, _>>()
+            .or_fail_with(|e| format!("Invalid script: {e}"))
+    }
+}
+
+fn current_image(canvas: &Canvas) -> orfail::Result<pati::Image> {
+    let image = canvas.image();
+    image.restore_image(image.version()).or_fail()
+}
+
+/// Sends pixels cut out of another file to the editor for placement.
+///
+/// The pixels follow the cursor in the editor until they are stamped by `draw`
+/// (which can be repeated) or dismissed by `cancel`.
+#[derive(Debug, clap::Args)]
+pub struct IncludeCommand {
+    /// Port number of the editor (defaults to `$PATICA_PORT`).
+    #[clap(short, long)]
+    port: Option<u16>,
+
+    /// Region to include (defaults to the bounding box of the image).
+    #[clap(long, conflicts_with = "anchor")]
+    region: Option<RegionArg>,
+
+    /// Includes the region between the `<ANCHOR>.start` and `<ANCHOR>.end` anchors.
+    #[clap(long)]
+    anchor: Option<String>,
+
+    /// Point placed at the cursor (defaults to the center of the region).
+    #[clap(long)]
+    origin: Option<PointArg>,
+
+    #[clap(long)]
+    flip: Option<FlipArg>,
+
+    /// Number of clockwise quarter turns.
+    #[clap(long, default_value_t = 0)]
+    rotate: u8,
+
+    include_file: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum FlipArg {
+    Horizontal,
+    Vertical,
+}
+
+impl IncludeCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let image = load_image(&self.include_file).or_fail()?;
+        let anchors = image.anchors();
+        let (start, end) = if let Some(region) = &self.region {
+            region.resolve(anchors).or_fail()?
+        } else if let Some(name) = &self.anchor {
+            RegionArg {
+                start: PointArg::Anchor(format!("{name}.start")),
+                end: PointArg::Anchor(format!("{name}.end")),
+            }
+            .resolve(anchors)
+            .or_fail()?
+        } else {
+            image
+                .bounding_box()
+                .or_fail_with(|()| "Empty image".to_owned())?
+        };
+        let origin = if let Some(origin) = &self.origin {
+            origin.resolve(anchors).or_fail()?
+        } else {
+            Point::new(
+                ((end.x as i32 - start.x as i32 + 1) / 2 + start.x as i32) as i16,
+                ((end.y as i32 - start.y as i32 + 1) / 2 + start.y as i32) as i16,
+            )
+        };
+
+        let pixels = image
+            .range_pixels(start..=end)
+            .map(|(point, color)| (point - origin, color))
+            .collect::<Vec<_>>();
+        (!pixels.is_empty()).or_fail_with(|()| "No pixels in the region".to_owned())?;
+
+        let mut commands = vec![CanvasCommand::Import(pixels)];
+        if let Some(flip) = self.flip {
+            commands.push(CanvasCommand::Flip(match flip {
+                FlipArg::Horizontal => FlipDirection::Horizontal,
+                FlipArg::Vertical => FlipDirection::Vertical,
+            }));
+        }
+        for _ in 0..self.rotate % 4 {
+            commands.push(CanvasCommand::Rotate);
+        }
+
+        let mut agent = connect_agent(self.port).or_fail()?;
+        for command in commands {
+            agent.command(command).or_fail()?.or_fail()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct QueryCommand {
+    /// Port number of the editor (defaults to `$PATICA_PORT`).
+    #[clap(short, long, conflicts_with = "file")]
+    port: Option<u16>,
+
+    /// Query the given file instead of a running editor.
+    #[clap(short, long)]
+    file: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    target: QueryTarget,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum QueryTarget {
+    Cursor,
+    Camera,
+    BrushColor,
+    BackgroundColor,
+    Scale,
+    Fps,
+    Anchors,
+    MetadataKeys,
+    Metadata {
+        key: String,
+    },
+    Pixel {
+        /// `x,y` point or anchor name.
+        point: PointArg,
+    },
+    /// Bounding box of the pixels.
+    Bounds,
+    Version,
+    /// Number of pixels of each color.
+    ColorHistogram,
+}
+
+impl QueryCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let value = if let Some(path) = &self.file {
+            let canvas = load_canvas(path).or_fail()?;
+            let query = self
+                .to_canvas_query(|| Ok(canvas.image().anchors().clone()))
+                .or_fail()?;
+            canvas.query(&query)
+        } else {
+            let mut agent = connect_agent(self.port).or_fail()?;
+            let anchors = |agent: &mut CanvasAgent| match agent.query(CanvasQuery::Anchors) {
+                Ok(CanvasQueryValue::Anchors(anchors)) => Ok(anchors),
+                Ok(value) => Err(orfail::Failure::new(format!(
+                    "Unexpected query value: {value:?}"
+                ))),
+                Err(e) => Err(e),
+            };
+            let query = self.to_canvas_query(|| anchors(&mut agent)).or_fail()?;
+            agent.query(query).or_fail()?
+        };
+
+        // Print only the inner value of `{"<query_name>": <value>}`.
+        let value = match serde_json::to_value(&value).or_fail()? {
+            serde_json::Value::Object(object) if object.len() == 1 => {
+                object.into_iter().next().map(|(_, v)| v).or_fail()?
+            }
+            value => value,
+        };
+        println!("{value}");
+        Ok(())
+    }
+
+    fn to_canvas_query<F>(&self, anchors: F) -> orfail::Result<CanvasQuery>
+    where
+        F: FnOnce() -> orfail::Result<BTreeMap<String, Point>>,
+    {
+        Ok(match &self.target {
+            QueryTarget::Cursor => CanvasQuery::Cursor,
+            QueryTarget::Camera => CanvasQuery::Camera,
+            QueryTarget::BrushColor => CanvasQuery::BrushColor,
+            QueryTarget::BackgroundColor => CanvasQuery::BackgroundColor,
+            QueryTarget::Scale => CanvasQuery::Scale,
+            QueryTarget::Fps => CanvasQuery::Fps,
+            QueryTarget::Anchors => CanvasQuery::Anchors,
+            QueryTarget::MetadataKeys => CanvasQuery::MetadataKeys,
+            QueryTarget::Metadata { key } => CanvasQuery::Metadata(key.clone()),
+            QueryTarget::Pixel { point } => {
+                let point = match point {
+                    PointArg::Point(point) => *point,
+                    PointArg::Anchor(_) => point.resolve(&anchors().or_fail()?).or_fail()?,
+                };
+                CanvasQuery::Pixel(point)
+            }
+            QueryTarget::Bounds => CanvasQuery::Bounds,
+            QueryTarget::Version => CanvasQuery::Version,
+            QueryTarget::ColorHistogram => CanvasQuery::ColorHistogram,
+        })
+    }
+}
+
+fn connect_agent(port: Option<u16>) -> orfail::Result<CanvasAgent> {
+    let port = if let Some(port) = port {
+        port
+    } else {
+        let port = std::env::var(ENV_PATICA_PORT)
+            .or_fail_with(|_| format!("Neither --port nor ${ENV_PATICA_PORT} is specified"))?;
+        port.parse::<u16>()
+            .or_fail_with(|e| format!("Invalid ${ENV_PATICA_PORT} value {port:?}: {e}"))?
+    };
+    CanvasAgent::connect(port).or_fail()
+}
+
+/// Embeds a region of another file as a frame at the cursor of the editor.
+///
+/// The frame is kept up-to-date while the embedded file changes,
+/// and can be removed by `{"remove":{"frame":"<NAME>"}}`.
+#[derive(Debug, clap::Args)]
+pub struct EmbedCommand {
+    /// Port number of the editor (defaults to `$PATICA_PORT`).
+    #[clap(short, long)]
+    port: Option<u16>,
+
+    #[clap(long = "top-left")]
+    top_left_anchor: String,
+
+    #[clap(long = "bottom-right")]
+    bottom_right_anchor: String,
+
+    /// Ticks at which the frame appears.
+    #[clap(long = "time", default_value_t = 0)]
+    start_ticks: u32,
+
+    /// Number of ticks during which the frame is visible.
+    #[clap(long = "duration", default_value_t = 1)]
+    duration_ticks: u32,
+
+    #[clap(long)]
+    name: String,
+
+    path: PathBuf,
+}
+
+impl EmbedCommand {
+    fn run(&self) -> orfail::Result<()> {
+        // The editor may run in another directory.
+        let path = self
+            .path
+            .canonicalize()
+            .or_fail_with(|e| format!("Failed to resolve path {}: {e}", self.path.display()))?;
+        let frame = Frame {
+            name: self.name.clone(),
+            path,
+            top_left_anchor: self.top_left_anchor.clone(),
+            bottom_right_anchor: self.bottom_right_anchor.clone(),
+            start_ticks: Ticks::new(self.start_ticks),
+            end_ticks: Ticks::new(self.start_ticks.saturating_add(self.duration_ticks)),
+        };
+        let mut agent = connect_agent(self.port).or_fail()?;
+        agent
+            .command(CanvasCommand::Embed(frame))
+            .or_fail()?
+            .or_fail()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ExportCommand {
+    path: PathBuf,
+
+    /// Output file path (`.bmp`, `.png` or `.svg`).
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl ExportCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let output = self
+            .output
+            .clone()
+            .unwrap_or_else(|| self.path.with_extension("bmp"));
+        let image = load_image(&self.path).or_fail()?;
+        let region = image
+            .bounding_box()
+            .unwrap_or((Point::ORIGIN, Point::ORIGIN));
+        export_image(&image, region, &output).or_fail()?;
+        println!("Exported to {}", output.display());
+        Ok(())
+    }
+}
+
+/// Writes the pixels of the given region (inclusive) to a `.bmp`, `.png` or `.svg` file.
+fn export_image(
+    image: &pati::Image,
+    (start, end): (Point, Point),
+    output: &Path,
+) -> orfail::Result<()> {
+    let is_svg = output.extension().is_some_and(|ext| ext == "svg");
+    let size = if is_svg {
+        None
+    } else {
+        Some(crate::png::image_size((start, end), NonZeroU8::MIN).or_fail()?)
+    };
+
+    let file = std::fs::File::create(output)
+        .or_fail_with(|e| format!("Failed to create file {}: {e}", output.display()))?;
+    let writer = BufWriter::new(file);
+    let pixels = image.range_pixels(start..=end);
+
+    if is_svg {
+        let background_color = image
+            .metadata()
+            .get(METADATA_BACKGROUND_COLOR)
+            .map(|v| serde_json::from_value(v.clone()))
+            .transpose()
+            .or_fail()?;
+        let mut cropped = pati::Image::new();
+        cropped.apply(&pati::ImageCommand::draw_pixels(pixels));
+        crate::svg::write_image(writer, &cropped, background_color).or_fail()?;
+    } else {
+        let write_image = if output.extension().is_some_and(|ext| ext == "png") {
+            crate::png::write_image
+        } else {
+            crate::bmp::write_image
+        };
+        let (width, height) = size.or_fail()?;
+        write_image(
+            writer,
+            width,
+            height,
+            pixels.map(|(point, color)| (point - start, color)),
+        )
+        .or_fail()?;
+    }
+    Ok(())
+}
+
+/// Follows the changes of a file and re-exports it whenever a new version is written.
+#[derive(Debug, clap::Args)]
+pub struct WatchCommand {
+    path: PathBuf,
+
+    /// Export target as `OUTPUT` or `REGION=OUTPUT` (e.g. `head.start..head.end=head.png`).
+    ///
+    /// `OUTPUT` is a `.bmp`, `.png` or `.svg` file, and the whole image is exported if `REGION` is omitted.
+    /// This option can be specified multiple times.
+    #[clap(long = "export", required = true)]
+    exports: Vec<ExportTarget>,
+
+    /// Milliseconds to wait for further edits before exporting.
+    #[clap(long, default_value_t = 200)]
+    debounce: u64,
+
+    /// Milliseconds between checks for new commands.
+    #[clap(long, default_value_t = 100)]
+    interval: u64,
+}
+
+impl WatchCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let file = std::fs::File::open(&self.path)
+            .or_fail_with(|e| format!("Failed to open file {}: {e}", self.path.display()))?;
+        let mut reader = ImageCommandReader::new(BufReader::new(file));
+        let mut image = pati::Image::new();
+        let debounce = Duration::from_millis(self.debounce);
+        let interval = Duration::from_millis(self.interval);
+
+        while let Some(command) = reader.read_command().or_fail()? {
+            image.apply(&command);
+        }
+        self.export(&image);
+
+        // `Some(_)` means that the image has been changed since the last export.
+        let mut last_changed = None;
+        loop {
+            while let Some(command) = reader.read_command().or_fail()? {
+                if image.apply(&command) {
+                    last_changed = Some(Instant::now());
+                }
+            }
+            if last_changed.is_some_and(|t| t.elapsed() >= debounce) {
+                self.export(&image);
+                last_changed = None;
+            }
+            std::thread::sleep(interval);
+        }
+    }
+
+    fn export(&self, image: &pati::Image) {
+        for target in &self.exports {
+            // Errors (e.g. missing anchors while editing) are reported without stopping the watch.
+            match target.export(image) {
+                Ok(()) => eprintln!("Exported to {}", target.output.display()),
+                Err(e) => eprintln!(
+                    "Failed to export to {}: {}",
+                    target.output.display(),
+                    e.message
+                ),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ExportTarget {
+    region: Option<RegionArg>,
+    output: PathBuf,
+}
+
+impl ExportTarget {
+    fn export(&self, image: &pati::Image) -> orfail::Result<()> {
+        let region = if let Some(region) = &self.region {
+            region.resolve(image.anchors()).or_fail()?
+        } else {
+            image
+                .bounding_box()
+                .or_fail_with(|()| "Empty image".to_owned())?
+        };
+        export_image(image, region, &self.output).or_fail()
+    }
+}
+
+impl std::str::FromStr for ExportTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((region, output)) = s.split_once('=') {
+            Ok(Self {
+                region: Some(region.parse()?),
+                output: PathBuf::from(output),
+            })
+        } else {
+            Ok(Self {
+                region: None,
+                output: PathBuf::from(s),
+            })
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ShowCommand {
+    path: PathBuf,
+
+    /// Region to show (e.g. `palette.start..palette.end` or `0,0..15,15`).
+    #[clap(long)]
+    region: Option<RegionArg>,
+
+    #[clap(long, value_enum, default_value_t)]
+    color: ColorMode,
+}
+
+impl ShowCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let image = load_image(&self.path).or_fail()?;
+        let region = if let Some(region) = &self.region {
+            region.resolve(image.anchors()).or_fail()?
+        } else if let Some(region) = image.bounding_box() {
+            region
+        } else {
+            return Ok(());
+        };
+        let background_color = image
+            .metadata()
+            .get(METADATA_BACKGROUND_COLOR)
+            .map(|v| serde_json::from_value(v.clone()))
+            .transpose()
+            .or_fail()?;
+
+        let stdout = std::io::stdout();
+        let mut stdout = BufWriter::new(stdout.lock());
+        crate::ansi::write_image(&mut stdout, &image, region, background_color, self.color)
+            .or_fail()?;
+        stdout.flush().or_fail()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum PaletteCommand {
+    /// Convert a palette file (`.gpl`, `.pal` or `.hex`) into a palette-grid `.pati` file.
+    Import {
+        palette_file: PathBuf,
+
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        /// Number of swatches per row.
+        #[clap(long)]
+        columns: Option<usize>,
+    },
+
+    /// Convert a palette-grid `.pati` file into a palette file (`.gpl`, `.pal` or `.hex`).
+    Export {
+        path: PathBuf,
+
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+}
+
+impl PaletteCommand {
+    fn run(&self) -> orfail::Result<()> {
+        match self {
+            Self::Import {
+                palette_file,
+                output,
+                columns,
+            } => {
+                let format = PaletteFormat::from_path(palette_file).or_fail()?;
+                let file = std::fs::File::open(palette_file).or_fail_with(|e| {
+                    format!("Failed to open file {}: {e}", palette_file.display())
+                })?;
+                let mut palette = Palette::read(BufReader::new(file), format).or_fail()?;
+                if columns.is_some() {
+                    palette.columns = *columns;
+                }
+
+                let output = output
+                    .clone()
+                    .unwrap_or_else(|| palette_file.with_extension("pati"));
+                let file = std::fs::File::create(&output).or_fail()?;
+                let mut writer = ImageCommandWriter::new(BufWriter::new(file));
+                for command in palette.to_image_commands(Point::ORIGIN).or_fail()? {
+                    writer.write_command(&command).or_fail()?;
+                }
+                println!("Imported to {}", output.display());
+            }
+            Self::Export { path, output } => {
+                let format = PaletteFormat::from_path(output).or_fail()?;
+                let image = load_image(path).or_fail()?;
+                let palette = Palette::from_image(&image).or_fail()?;
+                let file = std::fs::File::create(output).or_fail()?;
+                palette.write(BufWriter::new(file), format).or_fail()?;
+                println!("Exported to {}", output.display());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct TimelapseCommand {
+    path: PathBuf,
+
+    /// Output path (an animated GIF if it ends with `.gif`, otherwise a directory for PNG files).
+    #[clap(short, long)]
+    output: PathBuf,
+
+    /// Take a frame every N versions.
+    #[clap(long, default_value = "1", conflicts_with = "every_pixels")]
+    every_versions: NonZeroUsize,
+
+    /// Take a frame every time N or more pixels have been changed.
+    #[clap(long)]
+    every_pixels: Option<NonZeroUsize>,
+
+    #[clap(long, default_value = "1")]
+    scale: NonZeroU8,
+
+    /// Frame duration in hundredths of a second (GIF only).
+    #[clap(long, default_value_t = 10)]
+    delay: u16,
+
+    /// Number of extra frames to hold on the final image.
+    #[clap(long, default_value_t = 30)]
+    hold: u16,
+}
+
+impl TimelapseCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let image = load_versioned_image(&self.path).or_fail()?;
+        let sampling = if let Some(n) = self.every_pixels {
+            Sampling::Pixels(n)
+        } else {
+            Sampling::Versions(self.every_versions)
+        };
+        let mut timelapse = Timelapse::new(&image, sampling);
+        timelapse.set_scale(self.scale);
+        timelapse.set_background_color(
+            image
+                .metadata()
+                .get(METADATA_BACKGROUND_COLOR)
+                .map(|v| serde_json::from_value(v.clone()))
+                .transpose()
+                .or_fail()?,
+        );
+
+        if self.output.extension().is_some_and(|ext| ext == "gif") {
+            let file = std::fs::File::create(&self.output).or_fail()?;
+            timelapse
+                .write_gif(BufWriter::new(file), self.delay, self.hold)
+                .or_fail()?;
+        } else {
+            timelapse
+                .write_png_sequence(&self.output, self.hold)
+                .or_fail()?;
+        }
+        println!(
+            "Exported {} frames to {}",
+            timelapse.frames().len(),
+            self.output.display()
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RenderCommand {
+    path: PathBuf,
+
+    /// Output file path (`.png` or `.bmp`).
+    #[clap(short, long)]
+    output: PathBuf,
+
+    /// Region to render (defaults to the bounding box of the visible pixels).
+    #[clap(long)]
+    region: Option<RegionArg>,
+
+    #[clap(long, default_value = "1")]
+    scale: NonZeroU8,
+
+    /// Ticks used to decide which frames are visible.
+    #[clap(long, default_value_t = 0)]
+    tick: i32,
+}
+
+impl RenderCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let mut canvas = load_canvas(&self.path).or_fail()?;
+        canvas.command(&CanvasCommand::Tick(self.tick)).or_fail()?;
+
+        let (start, end) = if let Some(region) = &self.region {
+            region.resolve(canvas.image().anchors()).or_fail()?
+        } else {
+            Point::bounding_box(
+                canvas
+                    .visible_pixels(Point::MIN, Point::MAX)
+                    .map(|(p, _)| p),
+            )
+            .unwrap_or((Point::ORIGIN, Point::ORIGIN))
+        };
+        let (width, height) = crate::png::image_size((start, end), self.scale).or_fail()?;
+        let size = Size::from_wh(u32::from(width), u32::from(height));
+
+        let mut video_frame = VideoFrame::new(VideoFrameSpec {
+            pixel_format: PixelFormat::Rgb24,
+            resolution: size,
+            stride: size.width,
+        });
+        let mut screen = Screen::new(PagurusCanvas::new(&mut video_frame), size);
+        screen.set_viewport(start, self.scale);
+        crate::view::render_canvas(&canvas, &mut screen);
+
+        let pixels = size.iter().map(|pos| {
+            let (r, g, b) = video_frame.read_rgb(pos);
+            (Point::new(pos.x as i16, pos.y as i16), Color::rgb(r, g, b))
+        });
+        let writer = BufWriter::new(std::fs::File::create(&self.output).or_fail()?);
+        if self.output.extension().is_some_and(|ext| ext == "bmp") {
+            crate::bmp::write_image(writer, width, height, pixels).or_fail()?;
+        } else {
+            crate::png::write_image(writer, width, height, pixels).or_fail()?;
+        }
+        println!("Rendered to {}", self.output.display());
+        Ok(())
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct LogCommand {
+    path: PathBuf,
+
+    /// Versions to show (e.g. `10..20`, `10..` or `..20`; inclusive).
+    #[clap(long)]
+    versions: Option<VersionRangeArg>,
+
+    /// Shows only the commands of the given kinds.
+    #[clap(long, value_delimiter = ',')]
+    kind: Vec<CommandKind>,
+
+    /// Shows only the commands touching the given region.
+    #[clap(long)]
+    region: Option<RegionArg>,
+
+    /// Prints each entry as a JSON line.
+    #[clap(long)]
+    json: bool,
+}
+
+impl LogCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let image = load_versioned_image(&self.path).or_fail()?;
+        let region = self
+            .region
+            .as_ref()
+            .map(|r| r.resolve(image.anchors()))
+            .transpose()
+            .or_fail()?;
+        let range = self.versions.unwrap_or_default();
+
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        for (i, command) in image
+            .applied_commands(Default::default())
+            .iter()
+            .enumerate()
+        {
+            let version = i as u32 + 1;
+            if !range.contains(version) {
+                continue;
+            }
+            if !self.kind.is_empty() && !self.kind.contains(&CommandKind::of(command)) {
+                continue;
+            }
+            let entry = LogEntry::new(version, command);
+            if region.is_some_and(|region| !entry.intersects(region)) {
+                continue;
+            }
+
+            if self.json {
+                serde_json::to_writer(&mut stdout, &entry).or_fail()?;
+                writeln!(stdout).or_fail()?;
+            } else {
+                let kind = serde_json::to_value(entry.kind).or_fail()?;
+                let kind = kind.as_str().or_fail()?;
+                writeln!(
+                    stdout,
+                    "{:>6}  {kind:<6}  {}",
+                    entry.version,
+                    entry.summary()
+                )
+                .or_fail()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Restores the image at a past version.
+///
+/// By default, the commands reverting the image are appended to the file so that
+/// the history is kept (and a running editor follows the change).
+#[derive(Debug, clap::Args)]
+pub struct CheckoutCommand {
+    path: PathBuf,
+
+    /// Version to restore (see `patica log`).
+    version: u32,
+
+    /// Writes the image at the version to a new file instead of updating `PATH`.
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl CheckoutCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let image = load_versioned_image(&self.path).or_fail()?;
+        let latest = image.applied_commands(Version::default()).len() as u32;
+        (self.version <= latest).or_fail_with(|()| {
+            format!(
+                "No such version: {} (the latest version is {latest})",
+                self.version
+            )
+        })?;
+        let version = Version::default() + self.version;
+
+        let (commands, file) = if let Some(output) = &self.output {
+            let old = image.restore_image(version).or_fail()?;
+            let file = std::fs::File::create(output)
+                .or_fail_with(|e| format!("Failed to create file {}: {e}", output.display()))?;
+            (crate::history::snapshot_commands(&old), file)
+        } else {
+            let file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&self.path)
+                .or_fail_with(|e| format!("Failed to open file {}: {e}", self.path.display()))?;
+            (
+                crate::history::revert_commands(&image, version).or_fail()?,
+                file,
+            )
+        };
+
+        let mut writer = ImageCommandWriter::new(BufWriter::new(file));
+        for command in &commands {
+            writer.write_command(command).or_fail()?;
+        }
+        eprintln!("Wrote {} command(s)", commands.len());
+        Ok(())
+    }
+}
+
+/// Shows the differences between two files, or between two versions of a file.
+#[derive(Debug, clap::Args)]
+pub struct DiffCommand {
+    path: PathBuf,
+
+    /// File to compare with `PATH` (if omitted, versions of `PATH` are compared).
+    other_path: Option<PathBuf>,
+
+    /// Old version (defaults to the empty image).
+    #[clap(long, conflicts_with = "other_path")]
+    from: Option<u32>,
+
+    /// New version (defaults to the latest version).
+    #[clap(long, conflicts_with = "other_path")]
+    to: Option<u32>,
+
+    /// Prints only the number of changes.
+    #[clap(long)]
+    stat: bool,
+
+    /// Prints the differences as JSON.
+    #[clap(long, conflicts_with = "stat")]
+    json: bool,
+
+    /// Renders the differences to a PNG file.
+    ///
+    /// Added, removed and changed pixels are highlighted in green, red and yellow,
+    /// and unchanged pixels are faded.
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+
+    #[clap(long, default_value = "1")]
+    scale: NonZeroU8,
+}
+
+impl DiffCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let (old, new) = if let Some(other_path) = &self.other_path {
+            (
+                load_image(&self.path).or_fail()?,
+                load_image(other_path).or_fail()?,
+            )
+        } else {
+            let image = load_versioned_image(&self.path).or_fail()?;
+            let latest = image.applied_commands(Version::default()).len() as u32;
+            let restore = |version: u32| {
+                (version <= latest)
+                    .or_fail_with(|()| {
+                        format!("No such version: {version} (the latest version is {latest})")
+                    })
+                    .and_then(|()| image.restore_image(Version::default() + version).or_fail())
+            };
+            (
+                restore(self.from.unwrap_or(0)).or_fail()?,
+                restore(self.to.unwrap_or(latest)).or_fail()?,
+            )
+        };
+        let diff = ImageDiff::new(&old, &new);
+
+        if let Some(output) = &self.output {
+            self.render(&old, &new, &diff, output).or_fail()?;
+        }
+
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        if self.json {
+            serde_json::to_writer(&mut stdout, &diff).or_fail()?;
+            writeln!(stdout).or_fail()?;
+        } else if self.stat {
+            writeln!(
+                stdout,
+                "{} added, {} removed, {} changed pixel(s); {} anchor(s), {} metadata item(s) changed",
+                diff.added.len(),
+                diff.removed.len(),
+                diff.changed.len(),
+                diff.anchors.len(),
+                diff.metadata.len()
+            )
+            .or_fail()?;
+        } else {
+            write_diff(&mut stdout, &diff).or_fail()?;
+        }
+        Ok(())
+    }
+
+    fn render(
+        &self,
+        old: &pati::Image,
+        new: &pati::Image,
+        diff: &ImageDiff,
+        output: &Path,
+    ) -> orfail::Result<()> {
+        const ADDED: Color = Color::rgb(0, 200, 0);
+        const REMOVED: Color = Color::rgb(230, 0, 0);
+        const CHANGED: Color = Color::rgb(255, 200, 0);
+
+        let points = [old.bounding_box(), new.bounding_box()]
+            .into_iter()
+            .flatten()
+            .flat_map(|(start, end)| [start, end]);
+        let (start, end) = Point::bounding_box(points).unwrap_or((Point::ORIGIN, Point::ORIGIN));
+
+        let mut pixels = BTreeMap::new();
+        for (point, c) in new.range_pixels(start..=end) {
+            // Fade unchanged pixels by blending them with white.
+            let fade = |v: u8| ((v as u16 + 255 * 3) / 4) as u8;
+            pixels.insert(point, Color::rgb(fade(c.r), fade(c.g), fade(c.b)));
+        }
+        pixels.extend(diff.added.iter().map(|&(p, _)| (p, ADDED)));
+        pixels.extend(diff.removed.iter().map(|&(p, _)| (p, REMOVED)));
+        pixels.extend(diff.changed.iter().map(|&(p, _, _)| (p, CHANGED)));
+
+        let (width, height) = crate::png::image_size((start, end), self.scale).or_fail()?;
+        let scale = i32::from(self.scale.get());
+        let pixels = (0..i32::from(height)).flat_map(|y| {
+            let pixels = &pixels;
+            (0..i32::from(width)).map(move |x| {
+                let point = Point::new((x / scale) as i16, (y / scale) as i16) + start;
+                let color = pixels
+                    .get(&point)
+                    .copied()
+                    .unwrap_or(Color::rgb(255, 255, 255));
+                (Point::new(x as i16, y as i16), color)
+            })
+        });
+        let file = std::fs::File::create(output)
+            .or_fail_with(|e| format!("Failed to create file {}: {e}", output.display()))?;
+        crate::png::write_image(BufWriter::new(file), width, height, pixels).or_fail()?;
+        Ok(())
+    }
+}
+
+/// Writes the differences in the line-oriented format of `patica diff`.
+fn write_diff<W: Write>(mut writer: W, diff: &ImageDiff) -> orfail::Result<()> {
+    let p = |p: Point| format!("[{},{}]", p.x, p.y);
+    for &(point, color) in &diff.removed {
+        writeln!(writer, "- pixel {} {}", p(point), color_to_hex(color)).or_fail()?;
+    }
+    for &(point, color) in &diff.added {
+        writeln!(writer, "+ pixel {} {}", p(point), color_to_hex(color)).or_fail()?;
+    }
+    for &(point, old, new) in &diff.changed {
+        writeln!(
+            writer,
+            "~ pixel {} {} -> {}",
+            p(point),
+            color_to_hex(old),
+            color_to_hex(new)
+        )
+        .or_fail()?;
+    }
+    for c in &diff.anchors {
+        match (c.old, c.new) {
+            (None, Some(new)) => writeln!(writer, "+ anchor {} {}", c.name, p(new)),
+            (Some(old), None) => writeln!(writer, "- anchor {} {}", c.name, p(old)),
+            (Some(old), Some(new)) => {
+                writeln!(writer, "~ anchor {} {} -> {}", c.name, p(old), p(new))
+            }
+            (None, None) => Ok(()),
+        }
+        .or_fail()?;
+    }
+    for c in &diff.metadata {
+        match (&c.old, &c.new) {
+            (None, Some(new)) => writeln!(writer, "+ metadata {} {new}", c.name),
+            (Some(old), None) => writeln!(writer, "- metadata {} {old}", c.name),
+            (Some(old), Some(new)) => {
+                writeln!(writer, "~ metadata {} {old} -> {new}", c.name)
+            }
+            (None, None) => Ok(()),
+        }
+        .or_fail()?;
+    }
+    Ok(())
+}
+
+/// Prints a line-oriented summary of a file for `git diff` (see `patica git-setup`).
+#[derive(Debug, clap::Args)]
+pub struct GitTextconvCommand {
+    path: PathBuf,
+}
+
+impl GitTextconvCommand {
+    fn run(&self) -> orfail::Result<()> {
+        let image = load_versioned_image(&self.path).or_fail()?;
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        writeln!(
+            stdout,
+            "version {}",
+            image.applied_commands(Version::default()).len()
+        )
+        .or_fail()?;
+        for (name, value) in image.metadata() {
+            writeln!(stdout, "metadata {name} {value}").or_fail()?;
+        }
+        for (name, p) in image.anchors() {
+            writeln!(stdout, "anchor {name} [{},{}]", p.x, p.y).or_fail()?;
+        }
+        for (p, &color) in image.pixels() {
+            writeln!(stdout, "pixel [{},{}] {}", p.x, p.y, color_to_hex(color)).or_fail()?;
+        }
+        Ok(())
+    }
+}
+
+/// Three-way merges files for git (see `patica git-setup`).
+///
+/// The changes merged into `OURS` are appended to it so that its history is kept.
+/// Conflicting pixels keep our colors, and their colors are copied to a region to the right of
+/// the image between the `merge.conflict.start` and `merge.conflict.end` anchors
+/// (pixels they erased are shown in magenta there).
+/// Exits with a failure if there are conflicts.
+#[derive(Debug, clap::Args)]
+pub struct GitMergeDriverCommand {
+    /// Common ancestor (`%O`).
+    base: PathBuf,
+
+    /// Current version (`%A`), which is overwritten by the merge result.
+    ours: PathBuf,
+
+    /// Other branch's version (`%B`).
+    theirs: PathBuf,
+}
+
+impl GitMergeDriverCommand {
+    const ANCHOR_CONFLICT_START: &'static str = "merge.conflict.start";
+    const ANCHOR_CONFLICT_END: &'static str = "merge.conflict.end";
+    const ERASED_COLOR: Color = Color::rgb(255, 0, 255);
+
+    fn run(&self) -> orfail::Result<()> {
+        let base = load_image(&self.base).or_fail()?;
+        let ours = load_image(&self.ours).or_fail()?;
+        let theirs = load_image(&self.theirs).or_fail()?;
+
+        let mut merge = Merge::new(&base, &ours, &theirs);
+        if let Some((start, end)) = Point::bounding_box(merge.pixel_conflicts.iter().copied()) {
+            let right = merge.image.bounding_box().map_or(end.x, |(_, e)| e.x);
+            let offset = i16::try_from(i32::from(right) - i32::from(start.x) + 2)
+                .ok()
+                .filter(|&dx| end.x.checked_add(dx).is_some())
+                .map(|dx| Point::new(dx, 0))
+                .or_fail_with(|()| {
+                    "No room for the conflicting pixels to the right of the image".to_owned()
+                })?;
+            let pixels = merge.pixel_conflicts.iter().map(|&p| {
+                let color = theirs.get_pixel(p).unwrap_or(Self::ERASED_COLOR);
+                (p + offset, color)
+            });
+            let commands = [
+                pati::ImageCommand::draw_pixels(pixels),
+                pati::ImageCommand::anchor(Self::ANCHOR_CONFLICT_START, Some(start + offset)),
+                pati::ImageCommand::anchor(Self::ANCHOR_CONFLICT_END, Some(end + offset)),
+            ];
+            for command in &commands {
+                merge.image.apply(command);
+            }
+        }
+
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.ours)
+            .or_fail_with(|e| format!("Failed to open file {}: {e}", self.ours.display()))?;
+        let mut writer = ImageCommandWriter::new(BufWriter::new(file));
+        for command in ImageDiff::new(&ours, &merge.image).to_commands() {
+            writer.write_command(&command).or_fail()?;
+        }
+
+        if !merge.pixel_conflicts.is_empty() {
+            eprintln!(
+                "{} conflicting pixel(s); their colors are placed between the {:?} and {:?} anchors",
+                merge.pixel_conflicts.len(),
+                Self::ANCHOR_CONFLICT_START,
+                Self::ANCHOR_CONFLICT_END
+            );
+        }
+        for name in &merge.anchor_conflicts {
+            eprintln!("Conflicting anchor (ours is kept): {name}");
+        }
+        for name in &merge.metadata_conflicts {
+            eprintln!("Conflicting metadata (ours is kept): {name}");
+        }
+        (!merge.has_conflicts()).or_fail_with(|()| "Merge conflicts".to_owned())?;
+        Ok(())
+    }
+}
+
+/// Prints the git configuration to use `patica` for diffing and merging `.pati` files.
+#[derive(Debug, clap::Args)]
+pub struct GitSetupCommand {}
+
+impl GitSetupCommand {
+    fn run(&self) -> orfail::Result<()> {
+        println!(
+            r#"# Add to .gitattributes:
+*.pati diff=pati merge=pati
+
+# Add to .git/config (or ~/.gitconfig):
+[diff "pati"]
+    textconv = patica git-textconv
+[merge "pati"]
+    name = patica pixel-aware merge
+    driver = patica git-merge-driver %O %A %B"#
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct VersionRangeArg {
+    start: Option<u32>,
+    end: Option<u32>,
+}
+
+impl VersionRangeArg {
+    fn contains(self, version: u32) -> bool {
+        self.start.is_none_or(|v| v <= version) && self.end.is_none_or(|v| version <= v)
+    }
+}
+
+impl std::str::FromStr for VersionRangeArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |v: &str| {
+            (!v.is_empty())
+                .then(|| {
+                    v.parse::<u32>()
+                        .map_err(|e| format!("Invalid version {v:?}: {e}"))
+                })
+                .transpose()
+        };
+        if let Some((start, end)) = s.split_once("..") {
+            Ok(Self {
+                start: parse(start)?,
+                end: parse(end)?,
+            })
+        } else {
+            let version = parse(s)?;
+            Ok(Self {
+                start: version,
+                end: version,
+            })
+        }
+    }
+}
+
+/// Inclusive rectangular region whose corners are given as anchor names or `x,y` points.
+#[derive(Debug, Clone)]
+pub struct RegionArg {
+    start: PointArg,
+    end: PointArg,
+}
+
+impl RegionArg {
+    fn resolve(&self, anchors: &BTreeMap<String, Point>) -> orfail::Result<(Point, Point)> {
+        let start = self.start.resolve(anchors).or_fail()?;
+        let end = self.end.resolve(anchors).or_fail()?;
+        (start.x <= end.x && start.y <= end.y).or_fail_with(|()| {
+            format!(
+                "Empty region: start=[{},{}], end=[{},{}]",
+                start.x, start.y, end.x, end.y
+            )
+        })?;
+        Ok((start, end))
+    }
+}
+
+impl std::str::FromStr for RegionArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once("..")
+            .ok_or_else(|| format!("Invalid region {s:?} (expected `START..END`)"))?;
+        Ok(Self {
+            start: start.parse()?,
+            end: end.parse()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum PointArg {
+    Point(Point),
+    Anchor(String),
+}
+
+impl PointArg {
+    fn resolve(&self, anchors: &BTreeMap<String, Point>) -> orfail::Result<Point> {
+        match self {
+            Self::Point(point) => Ok(*point),
+            Self::Anchor(name) => anchors
+                .get(name)
+                .copied()
+                .or_fail_with(|()| format!("No such anchor: {name}")),
+        }
+    }
+}
+
+impl std::str::FromStr for PointArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((x, y)) = s.split_once(',') {
+            if let (Ok(x), Ok(y)) = (x.trim().parse(), y.trim().parse()) {
+                return Ok(Self::Point(Point::new(x, y)));
+            }
+        }
+        if s.is_empty() {
+            return Err("Empty anchor name".to_owned());
+        }
+        Ok(Self::Anchor(s.to_owned()))
+    }
+}
+
+/// Loads a canvas from the given file without opening it for writing.
+///
+/// The pixels of the embedded frames are also loaded from their files.
+fn load_canvas<P: AsRef<Path>>(path: &P) -> orfail::Result<Canvas> {
+    let file = std::fs::File::open(path)
+        .or_fail_with(|e| format!("Failed to open file {}: {e}", path.as_ref().display()))?;
+    let mut reader = ImageCommandReader::new(BufReader::new(file));
+    let mut canvas = Canvas::new();
+    while let Some(command) = reader.read_command().or_fail()? {
+        canvas.replay(&command);
+    }
+
+    let base_dir = path.as_ref().parent().unwrap_or(Path::new(""));
+    EmbeddedFiles::new(base_dir).sync(canvas.frames_mut());
+    for (name, frame) in canvas.frames() {
+        if let Some(e) = &frame.error {
+            eprintln!("Warning: frame {name:?} may be out of date: {e}");
+        }
+    }
+    Ok(canvas)
+}
+
+fn load_versioned_image<P: AsRef<Path>>(path: &P) -> orfail::Result<pati::VersionedImage> {
+    let file = std::fs::File::open(path)
+        .or_fail_with(|e| format!("Failed to open file {}: {e}", path.as_ref().display()))?;
+    let mut reader = ImageCommandReader::new(BufReader::new(file));
+    let mut image = pati::VersionedImage::new();
+    while let Some(command) = reader.read_command().or_fail()? {
+        image.apply(&command);
+    }
+    Ok(image)
+}
+
+fn load_image<P: AsRef<Path>>(path: &P) -> orfail::Result<pati::Image> {
+    let file = std::fs::File::open(path)
+        .or_fail_with(|e| format!("Failed to open file {}: {e}", path.as_ref().display()))?;
+    let mut reader = ImageCommandReader::new(BufReader::new(file));
+    let mut image = pati::Image::new();
+    while let Some(command) = reader.read_command().or_fail()? {
+        image.apply(&command);
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_script() {
+        let dir = std::env::temp_dir().join(format!("patica-test-apply-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("image.pati");
+        let script = dir.join("script.jsonl");
+        CanvasFile::open(&path, true).unwrap();
+        let apply = |dry_run| {
+            let mut output = Vec::new();
+            ApplyCommand {
+                path: path.clone(),
+                script: script.clone(),
+                dry_run,
+                json: false,
+            }
+            .apply(&mut output)
+            .map(|()| String::from_utf8(output).unwrap())
+        };
+
+        std::fs::write(
+            &script,
+            r#"{"dip": [255, 0, 0]} {"move": [1, 2]} "draw" {"mark": "rectangle"}
+               {"move": [1, 1]} "cut" {"move": [0, 2]} "draw""#,
+        )
+        .unwrap();
+        assert_eq!(
+            apply(true).unwrap(),
+            "+ pixel [1,4] #ff0000\n+ metadata patica.brush_color [255,0,0]\n"
+        );
+        assert!(load_image(&path).unwrap().pixels().is_empty());
+        assert_eq!(apply(false).unwrap(), "");
+        let image = load_image(&path).unwrap();
+        let red = Color::rgb(255, 0, 0);
+        assert_eq!(image.pixels().len(), 1);
+        assert_eq!(image.get_pixel(Point::new(1, 4)), Some(red));
+
+        // Nothing is written if any command fails.
+        std::fs::write(&script, r#"{"move": [-1, -4]} "erase" "copy""#).unwrap();
+        let error = apply(false).unwrap_err();
+        assert!(
+            error.message.contains("Command #3 failed"),
+            "{}",
+            error.message
+        );
+        assert_eq!(load_image(&path).unwrap().pixels().len(), 1);
+
+        std::fs::write(&script, r#"{"jump": 1}"#).unwrap();
+        assert!(apply(false).is_err());
+
+        // Commands validated against an old version are not written.
+        let mut file = CanvasFile::open(&path, false).unwrap();
+        let version = file.canvas().image().version();
+        let mut other = CanvasFile::open(&path, false).unwrap();
+        other.command(&CanvasCommand::Dip(red)).unwrap();
+        other.command(&CanvasCommand::Draw).unwrap();
+        assert!(file.commands_at(version, &[CanvasCommand::Erase]).is_err());
+        assert_eq!(load_image(&path).unwrap().pixels().len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merge_conflicts() {
+        let dir = std::env::temp_dir().join(format!("patica-test-merge-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (red, blue) = (Color::rgb(255, 0, 0), Color::rgb(0, 0, 255));
+        let write = |name: &str, pixels: &[(Point, Color)]| {
+            let path = dir.join(name);
+            let file = std::fs::File::create(&path).unwrap();
+            let mut writer = ImageCommandWriter::new(file);
+            let command = pati::ImageCommand::draw_pixels(pixels.iter().copied());
+            writer.write_command(&command).unwrap();
+            path
+        };
+        let merge = |base: &[(Point, Color)], ours, theirs| {
+            GitMergeDriverCommand {
+                base: write("base.pati", base),
+                ours: write("ours.pati", ours),
+                theirs: write("theirs.pati", theirs),
+            }
+            .run()
+        };
+
+        // Ours changes (0, 0) and (1, 0), while theirs changes (0, 0) and erases (1, 0).
+        let base = [(Point::new(0, 0), red), (Point::new(1, 0), red)];
+        let ours = [(Point::new(0, 0), blue), (Point::new(1, 0), blue)];
+        let theirs = [(Point::new(0, 0), Color::rgb(0, 255, 0))];
+        assert!(merge(&base, &ours, &theirs).is_err());
+        let image = load_image(&dir.join("ours.pati")).unwrap();
+        assert_eq!(image.get_pixel(Point::new(0, 0)), Some(blue));
+        assert_eq!(image.get_pixel(Point::new(1, 0)), Some(blue));
+        assert_eq!(
+            image.get_pixel(Point::new(3, 0)),
+            Some(Color::rgb(0, 255, 0))
+        );
+        assert_eq!(
+            image.get_pixel(Point::new(4, 0)),
+            Some(GitMergeDriverCommand::ERASED_COLOR)
+        );
+        assert_eq!(
+            image
+                .anchors()
+                .get(GitMergeDriverCommand::ANCHOR_CONFLICT_END),
+            Some(&Point::new(4, 0))
+        );
+
+        // No room for the conflicting pixels.
+        let far = (Point::new(i16::MAX, 0), red);
+        let base = [(Point::new(i16::MIN, 0), red), far];
+        let ours = [(Point::new(i16::MIN, 0), blue), far];
+        let theirs = [(Point::new(i16::MIN, 0), Color::rgb(0, 255, 0)), far];
+        let error = merge(&base, &ours, &theirs).unwrap_err();
+        assert!(error.message.contains("No room"), "{}", error.message);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
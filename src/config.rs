@@ -1,44 +1,135 @@
 use orfail::{Failure, OrFail};
 use pagurus::event::{self, KeyEvent};
-use paticanvas::CanvasCommand;
+use paticanvas::{CanvasCommand, CanvasMode};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+const PROJECT_CONFIG_FILE_NAME: &str = ".patica.json";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Key bindings available in all modes.
     #[serde(default)]
     pub key: KeyConfig,
 
+    /// Key bindings for specific modes (these take precedence over `key`).
+    #[serde(default)]
+    pub mode_key: BTreeMap<CanvasMode, KeyConfig>,
+
     #[serde(default)]
     pub on_create: Option<CanvasCommand>,
 
     #[serde(default)]
     pub on_open: Option<CanvasCommand>,
+
+    /// Status line shown at the top of the editor.
+    ///
+    /// `Some(None)` comes from an explicit `null`, which hides the status line,
+    /// while `None` (the key is omitted) keeps the one of the lower layers.
+    #[serde(default, deserialize_with = "deserialize_explicit")]
+    pub status_line: Option<Option<StatusLineConfig>>,
+
+    /// Palette panel shown next to the canvas.
+    #[serde(default)]
+    pub palette: PaletteConfig,
+}
+
+impl Config {
+    /// Loads the config for the given file.
+    ///
+    /// The following layers are merged in order (later ones take precedence,
+    /// and key bindings are merged key by key):
+    ///
+    /// 1. Built-in defaults (`default-config.json`)
+    /// 2. User config (`~/.config/patica.json`)
+    /// 3. Project config (`.patica.json` in the nearest ancestor directory of `path`)
+    /// 4. `explicit_path` (e.g., `--config`)
+    pub fn load<P: AsRef<Path>>(path: P, explicit_path: Option<&Path>) -> orfail::Result<Self> {
+        let mut config = Self::default();
+        let layers = [user_config_path(), project_config_path(path.as_ref())];
+        for layer_path in layers.into_iter().flatten() {
+            if layer_path.exists() {
+                config.merge(Self::load_file(&layer_path).or_fail()?);
+            }
+        }
+        if let Some(layer_path) = explicit_path {
+            config.merge(Self::load_file(layer_path).or_fail()?);
+        }
+        Ok(config)
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> orfail::Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .or_fail_with(|e| format!("Failed to read config file {}: {e}", path.display()))?;
+        serde_json::from_str(&json).or_fail_with(|e| {
+            format!(
+                "Failed to parse config file: path={}, reason={e}",
+                path.display()
+            )
+        })
+    }
+
+    /// Overrides this config with the items specified in `other`.
+    pub fn merge(&mut self, other: Self) {
+        self.key.merge(other.key);
+        for (mode, key) in other.mode_key {
+            self.mode_key.entry(mode).or_default().merge(key);
+        }
+        if other.on_create.is_some() {
+            self.on_create = other.on_create;
+        }
+        if other.on_open.is_some() {
+            self.on_open = other.on_open;
+        }
+        if other.status_line.is_some() {
+            self.status_line = other.status_line;
+        }
+        self.palette.merge(other.palette);
+    }
+}
+
+/// Finds `.patica.json` by walking up from the directory containing the given file.
+pub fn project_config_path(path: &Path) -> Option<PathBuf> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let dir = dir.unwrap_or(Path::new(".")).canonicalize().ok()?;
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Gets the directory for the user's files (`$XDG_CONFIG_HOME/patica` or `~/.config/patica`).
+pub fn config_dir() -> Option<PathBuf> {
+    Some(xdg_config_home()?.join("patica"))
 }
 
-// impl Config {
-//     pub fn load_config_file() -> orfail::Result<Option<Self>> {
-//         let Ok(home_dir) = std::env::var("HOME") else {
-//             return Ok(None);
-//         };
-
-//         let path = Path::new(&home_dir).join(".config").join("patica.json");
-//         if !path.exists() {
-//             return Ok(None);
-//         }
-
-//         let json = std::fs::read_to_string(&path)
-//             .or_fail_with(|e| format!("Failed to read config file {}: {e}", path.display()))?;
-//         serde_json::from_str(&json)
-//             .or_fail_with(|e| {
-//                 format!(
-//                     "Failed to parse config file: path={}, reason={e}",
-//                     path.display()
-//                 )
-//             })
-//             .map(Some)
-//     }
-// }
+/// Gets the path of the user config file (`$XDG_CONFIG_HOME/patica.json` or `~/.config/patica.json`).
+///
+/// Note that it is placed next to [`config_dir()`], not in it.
+pub fn user_config_path() -> Option<PathBuf> {
+    Some(xdg_config_home()?.join("patica.json"))
+}
+
+fn xdg_config_home() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(PathBuf::from(dir))
+    } else {
+        Some(Path::new(&std::env::var_os("HOME")?).join(".config"))
+    }
+}
+
+/// Deserializes a present value (including `null`) as `Some(_)` so that it can be told apart
+/// from a missing key (which falls back to `None` by `#[serde(default)]`).
+fn deserialize_explicit<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
 
 impl Default for Config {
     fn default() -> Self {
@@ -47,24 +138,201 @@ impl Default for Config {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct KeyConfig(BTreeMap<Key, CanvasCommand>);
+pub struct StatusLineConfig {
+    /// Items to show (from left to right).
+    pub items: Vec<StatusItem>,
+
+    /// Anchor that the `relative_cursor` item is relative to.
+    #[serde(default)]
+    pub anchor: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusItem {
+    /// Current mode (`neutral`, `marking`, `editing` or `playing`).
+    Mode,
+
+    /// Cursor position (e.g., `3,-2`).
+    Cursor,
+
+    /// Cursor position relative to `anchor` (e.g., `head:1,0`; omitted if the anchor does not exist).
+    RelativeCursor,
+
+    /// Brush color (a swatch and its hex code).
+    Brush,
+
+    /// Background color (a swatch and its hex code).
+    Background,
+
+    /// Scale (e.g., `x2`).
+    Scale,
+
+    /// Version of the image (e.g., `v120`).
+    Version,
+
+    /// Whether all the applied commands have been written to the file (`saved` or `unsaved:N`),
+    /// preceded by the number of embedded frames that failed to sync if any (e.g., `stale:1`).
+    Sync,
+
+    /// Kind and number of points of the active marker (e.g., `rectangle:12`; omitted if not marking).
+    Marker,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PaletteConfig {
+    /// Palette file (`.gpl`, `.pal`, `.hex` or `.pati`).
+    ///
+    /// If omitted, the region between the `palette.start` and `palette.end` anchors of
+    /// the opened canvas is used (and the panel is not shown if the anchors are missing).
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+
+    /// Side of the screen where the panel is docked (defaults to `right`).
+    #[serde(default)]
+    pub dock: Option<Dock>,
+
+    /// Key bindings for the panel (these take precedence over `key`).
+    #[serde(default)]
+    pub key: BTreeMap<Key, PaletteAction>,
+}
+
+impl PaletteConfig {
+    /// Overrides this config with the items specified in `other`.
+    pub fn merge(&mut self, other: Self) {
+        if other.path.is_some() {
+            self.path = other.path;
+        }
+        if other.dock.is_some() {
+            self.dock = other.dock;
+        }
+        self.key.extend(other.key);
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dock {
+    Left,
+    #[default]
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaletteAction {
+    /// Moves the selection to the previous color.
+    Left,
+
+    /// Moves the selection to the next color.
+    Right,
+
+    /// Moves the selection to the previous row.
+    Up,
+
+    /// Moves the selection to the next row.
+    Down,
+
+    /// Sets the selected color as the brush color.
+    Select,
+
+    /// Sets the N-th (1-origin) recently used color as the brush color.
+    Recent(usize),
+
+    /// Shows or hides the panel.
+    Toggle,
+
+    /// Unbinds the key (it is handled by `key` instead).
+    None,
+}
+
+/// Key bindings (each key sequence is bound to a sequence of commands).
+///
+/// Keys in a sequence are separated by spaces (e.g., `"g g"`).
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct KeyConfig(BTreeMap<KeySequence, Vec<CanvasCommand>>);
 
 impl KeyConfig {
-    pub fn get_command(&self, key: KeyEvent) -> Option<&CanvasCommand> {
-        self.0.get(&Key(key))
+    pub fn get_commands(&self, keys: &[Key]) -> Option<&[CanvasCommand]> {
+        self.0
+            .get(&KeySequence(keys.to_vec()))
+            .map(|c| c.as_slice())
+    }
+
+    /// Returns `true` if there are bindings for longer sequences starting with `keys`.
+    pub fn is_prefix(&self, keys: &[Key]) -> bool {
+        self.0
+            .range(KeySequence(keys.to_vec())..)
+            .take_while(|(seq, _)| seq.0.starts_with(keys))
+            .any(|(seq, _)| seq.0.len() > keys.len())
+    }
+
+    /// Overrides the bindings of the keys in `other` (an empty sequence unbinds the key).
+    pub fn merge(&mut self, other: Self) {
+        for (key, commands) in other.0 {
+            if commands.is_empty() {
+                self.0.remove(&key);
+            } else {
+                self.0.insert(key, commands);
+            }
+        }
     }
 }
 
-impl Default for KeyConfig {
-    fn default() -> Self {
-        Config::default().key
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeySequence(Vec<Key>);
+
+impl std::fmt::Display for KeySequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, key) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{key}")?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+impl From<KeySequence> for String {
+    fn from(keys: KeySequence) -> Self {
+        keys.to_string()
+    }
+}
+
+impl TryFrom<String> for KeySequence {
+    type Error = Failure;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if s == " " {
+            // For backward compatibility.
+            return Ok(Self(vec![Key::try_from(s).or_fail()?]));
+        }
+        let keys = s
+            .split(' ')
+            .filter(|k| !k.is_empty())
+            .map(|k| Key::try_from(k.to_owned()))
+            .collect::<Result<Vec<_>, _>>()?;
+        (!keys.is_empty()).or_fail_with(|()| format!("Empty key sequence: {s:?}"))?;
+        Ok(Self(keys))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(try_from = "String", into = "String")]
 pub struct Key(KeyEvent);
 
+impl Key {
+    pub const fn new(key: KeyEvent) -> Self {
+        Self(key)
+    }
+
+    pub fn get(self) -> KeyEvent {
+        self.0
+    }
+}
+
 impl PartialOrd for Key {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -73,10 +341,40 @@ impl PartialOrd for Key {
 
 impl Ord for Key {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.to_string().cmp(&other.to_string())
+        fn rank(key: event::Key) -> u8 {
+            match key {
+                event::Key::Left => 0,
+                event::Key::Right => 1,
+                event::Key::Down => 2,
+                event::Key::Up => 3,
+                event::Key::Return => 4,
+                event::Key::Backspace => 5,
+                event::Key::Delete => 6,
+                event::Key::Tab => 7,
+                event::Key::BackTab => 8,
+                event::Key::Esc => 9,
+                event::Key::Char(_) => 10,
+                _ => 11,
+            }
+        }
+
+        let (a, b) = (self.0, other.0);
+        (a.ctrl, a.alt, rank(a.key))
+            .cmp(&(b.ctrl, b.alt, rank(b.key)))
+            .then_with(|| match (a.key, b.key) {
+                (event::Key::Char(c0), event::Key::Char(c1)) => c0.cmp(&c1),
+                // `Other` and keys added in future versions of pagurus.
+                (k0, k1) => format!("{k0:?}").cmp(&format!("{k1:?}")),
+            })
     }
 }
 
+/// Keys that are commonly found on keyboards but not reported by the terminal backend.
+const UNSUPPORTED_KEYS: &[&str] = &[
+    "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12", "Home", "End",
+    "PageUp", "PageDown", "Insert",
+];
+
 impl std::fmt::Display for Key {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.0.ctrl {
@@ -96,8 +394,13 @@ impl std::fmt::Display for Key {
             event::Key::Tab => write!(f, "Tab"),
             event::Key::BackTab => write!(f, "BackTab"),
             event::Key::Esc => write!(f, "Esc"),
+            event::Key::Char(' ') => write!(f, "Space"),
+            event::Key::Char(c) if c.is_control() || c.is_whitespace() => {
+                write!(f, "U+{:04X}", c as u32)
+            }
             event::Key::Char(c) => write!(f, "{}", c),
-            _ => unreachable!(),
+            // `Other` and keys added in future versions of pagurus.
+            _ => write!(f, "Unknown"),
         }
     }
 }
@@ -111,74 +414,189 @@ impl From<Key> for String {
 impl TryFrom<String> for Key {
     type Error = Failure;
 
+    /// Parses a key such as `a`, `Ctrl+Alt+x`, `Shift+Tab` (= `BackTab`) or `U+00E9` (= `é`).
     fn try_from(s: String) -> Result<Self, Self::Error> {
         let mut ctrl = false;
         let mut alt = false;
-        let mut i = 0;
+        let mut shift = false;
+        let mut last = s.as_str();
         loop {
-            if s[i..].starts_with("Ctrl+") {
+            if let Some(rest) = last.strip_prefix("Ctrl+") {
                 ctrl = true;
-                i += 5;
-            } else if s[i..].starts_with("Alt+") {
+                last = rest;
+            } else if let Some(rest) = last.strip_prefix("Alt+") {
                 alt = true;
-                i += 4;
+                last = rest;
+            } else if let Some(rest) = last.strip_prefix("Shift+") {
+                shift = true;
+                last = rest;
             } else {
                 break;
             }
         }
 
-        let last = &s[i..];
-        let key = match last {
-            "Enter" => event::Key::Return,
+        let mut key = match last {
+            "Enter" | "Return" => event::Key::Return,
             "Left" => event::Key::Left,
             "Right" => event::Key::Right,
             "Up" => event::Key::Up,
             "Down" => event::Key::Down,
             "Backspace" => event::Key::Backspace,
-            "Delete" => event::Key::Delete,
+            "Delete" | "Del" => event::Key::Delete,
             "Tab" => event::Key::Tab,
             "BackTab" => event::Key::BackTab,
-            "Esc" => event::Key::Esc,
-            _ if last.chars().count() == 1 => match last.chars().next().or_fail()? {
-                c @ ('a'..='z'
-                | 'A'..='Z'
-                | '0'..='9'
-                | ' '
-                | '+'
-                | '-'
-                | '_'
-                | '('
-                | ')'
-                | '{'
-                | '}'
-                | '['
-                | ']'
-                | '<'
-                | '>'
-                | ';'
-                | ':'
-                | '='
-                | '.'
-                | ','
-                | '!'
-                | '?'
-                | '/'
-                | '@'
-                | '#'
-                | '$'
-                | '%'
-                | '^'
-                | '&'
-                | '*'
-                | '"'
-                | '\''
-                | '`'
-                | '~') => event::Key::Char(c),
-                _ => return Err(Failure::new(format!("Unknown key: {last:?}"))),
-            },
+            "Esc" | "Escape" => event::Key::Esc,
+            "Space" => event::Key::Char(' '),
+            _ if last.chars().count() == 1 => event::Key::Char(last.chars().next().or_fail()?),
+            _ if last.starts_with("U+") => u32::from_str_radix(&last[2..], 16)
+                .ok()
+                .and_then(char::from_u32)
+                .map(event::Key::Char)
+                .or_fail_with(|()| format!("Invalid code point: {last:?}"))?,
+            _ if UNSUPPORTED_KEYS.contains(&last) => {
+                return Err(Failure::new(format!(
+                    "Key not reported by the terminal: {last:?}"
+                )));
+            }
             _ => return Err(Failure::new(format!("Unknown key: {last:?}"))),
         };
 
+        if shift {
+            // Terminals report shifted keys as different keys instead of a modifier.
+            key = match key {
+                event::Key::Tab => event::Key::BackTab,
+                event::Key::Char(c) if c.is_lowercase() && c.to_uppercase().count() == 1 => {
+                    event::Key::Char(c.to_uppercase().next().or_fail()?)
+                }
+                event::Key::Char(c) if c.is_uppercase() => key,
+                _ => {
+                    return Err(Failure::new(format!(
+                        "Shift+{last} cannot be distinguished from {last}"
+                    )));
+                }
+            };
+        }
+
         Ok(Self(KeyEvent { key, ctrl, alt }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(s: &str) -> Vec<Key> {
+        KeySequence::try_from(s.to_owned()).unwrap().0
+    }
+
+    #[test]
+    fn merge_key_bindings() {
+        let mut config = Config::default();
+        assert!(config.key.get_commands(&key("q")).is_some());
+
+        let layer: Config = serde_json::from_str(
+            r#"{"key": {"q": [], "w": ["draw"]}, "mode_key": {"marking": {"g g": ["erase"]}}}"#,
+        )
+        .unwrap();
+        config.merge(layer);
+        assert!(config.key.get_commands(&key("q")).is_none());
+        assert!(config.key.get_commands(&key("w")).is_some());
+        assert!(config.key.get_commands(&key("i")).is_some());
+
+        let marking = &config.mode_key[&CanvasMode::Marking];
+        assert!(marking.is_prefix(&key("g")));
+        assert!(marking.get_commands(&key("g g")).is_some());
+    }
+
+    #[test]
+    fn hide_status_line() {
+        let mut config = Config::default();
+        assert!(matches!(config.status_line, Some(Some(_))));
+
+        config.merge(serde_json::from_str(r#"{"key": {}}"#).unwrap());
+        assert!(matches!(config.status_line, Some(Some(_))));
+
+        config.merge(serde_json::from_str(r#"{"status_line": null}"#).unwrap());
+        assert!(matches!(config.status_line, Some(None)));
+    }
+
+    #[test]
+    fn key_sequence_roundtrip() {
+        for s in ["g g", "Ctrl+x Space", "Space", "Alt+Enter Esc"] {
+            let seq = KeySequence::try_from(s.to_owned()).unwrap();
+            assert_eq!(seq.to_string(), s);
+        }
+        assert_eq!(key(" "), key("Space"));
+    }
+
+    #[test]
+    fn key_roundtrip() {
+        let keys = [
+            event::Key::Left,
+            event::Key::Right,
+            event::Key::Down,
+            event::Key::Up,
+            event::Key::Return,
+            event::Key::Backspace,
+            event::Key::Delete,
+            event::Key::Tab,
+            event::Key::BackTab,
+            event::Key::Esc,
+        ]
+        .into_iter()
+        .chain(
+            [
+                'a', 'Z', '0', '+', ' ', 'é', 'ß', 'あ', '\u{1}', '\u{3000}', 'U',
+            ]
+            .map(event::Key::Char),
+        );
+        for key in keys {
+            for (ctrl, alt) in [(false, false), (true, false), (false, true), (true, true)] {
+                let key = Key(KeyEvent { key, ctrl, alt });
+                let s = key.to_string();
+                assert_eq!(Key::try_from(s.clone()).ok(), Some(key), "{s}");
+            }
+        }
+    }
+
+    #[test]
+    fn key_aliases() {
+        let parse = |s: &str| Key::try_from(s.to_owned()).map(|k| k.to_string());
+        assert_eq!(parse("Shift+a").ok().as_deref(), Some("A"));
+        assert_eq!(parse("Shift+A").ok().as_deref(), Some("A"));
+        assert_eq!(
+            parse("Ctrl+Shift+Tab").ok().as_deref(),
+            Some("Ctrl+BackTab")
+        );
+        assert_eq!(parse("Alt+Ctrl+x").ok().as_deref(), Some("Ctrl+Alt+x"));
+        assert_eq!(parse("Ctrl++").ok().as_deref(), Some("Ctrl++"));
+        assert_eq!(parse("Return").ok().as_deref(), Some("Enter"));
+        assert_eq!(parse("U+00E9").ok().as_deref(), Some("é"));
+        assert!(parse("Shift+1").is_err());
+        assert!(parse("F1").is_err());
+        assert!(parse("Foo").is_err());
+        assert!(parse("Unknown").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn key_order() {
+        let key = |key| Key::new(KeyEvent::from(key));
+        let keys = [
+            key(event::Key::Other),
+            key(event::Key::Char('a')),
+            key(event::Key::Char('b')),
+            key(event::Key::Tab),
+            Key(KeyEvent {
+                key: event::Key::Char('a'),
+                ctrl: true,
+                alt: false,
+            }),
+        ];
+        for k0 in keys {
+            for k1 in keys {
+                assert_eq!(k0.cmp(&k1).is_eq(), k0 == k1, "{k0:?} {k1:?}");
+            }
+        }
+    }
+}
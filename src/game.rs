@@ -1,4 +1,7 @@
-use crate::{model::Model, screen::Screen, view::View};
+use crate::{
+    config::Config, keymap::Keymap, model::Model, palette_panel::PalettePanel, screen::Screen,
+    view::View,
+};
 use orfail::OrFail;
 use pagurus::{
     event::{Event, TimeoutTag},
@@ -6,6 +9,7 @@ use pagurus::{
     video::VideoFrame,
     System,
 };
+use paticanvas::{CanvasCommand, CanvasMode};
 use std::time::Duration;
 
 const TICK_TIMEOUT_TAG: TimeoutTag = TimeoutTag::new(0);
@@ -26,6 +30,15 @@ impl Game {
         }
     }
 
+    pub fn set_config(&mut self, config: Config) {
+        self.view.set_keymap(Keymap::new(&config));
+        self.view.set_status_line(config.status_line.flatten());
+    }
+
+    pub fn set_palette_panel(&mut self, palette_panel: PalettePanel) {
+        self.view.set_palette_panel(Some(palette_panel));
+    }
+
     pub fn model(&self) -> &Model {
         &self.model
     }
@@ -61,12 +74,16 @@ impl<S: System> pagurus::Game<S> for Game {
         let ticked = match event {
             Event::WindowResized(size) => {
                 self.video_frame = VideoFrame::new(system.video_init(size));
+                self.view.handle_resize(size);
                 None
             }
             Event::Timeout(TICK_TIMEOUT_TAG) => {
                 let now = system.clock_game_time();
                 self.model.sync().or_fail()?;
-                // TODO: self.model.tick();
+                self.view.handle_tick(&self.model, now);
+                if self.model.canvas().mode() == CanvasMode::Playing {
+                    self.model.command(&CanvasCommand::Tick(1)).or_fail()?;
+                }
                 Some(now)
             }
             _ => {
@@ -92,7 +109,7 @@ impl<S: System> pagurus::Game<S> for Game {
 
 // impl Game {
 //     pub fn set_config(&mut self, config: Config) {
-//         self.view.set_key_config(config.key);
+//         self.view.set_keymap(Keymap::new(&config));
 
 //         self.model
 //             .apply(&Command::BackgroundColor(config.initial.background_color));
@@ -0,0 +1,367 @@
+use pati::{Color, Image, ImageCommand, PatchEntry, Point, Version, VersionedImage};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Kind of [`ImageCommand`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandKind {
+    Patch,
+    Anchor,
+    Put,
+}
+
+impl CommandKind {
+    pub fn of(command: &ImageCommand) -> Self {
+        match command {
+            ImageCommand::Patch(_) => Self::Patch,
+            ImageCommand::Anchor { .. } => Self::Anchor,
+            ImageCommand::Put { .. } => Self::Put,
+        }
+    }
+}
+
+/// Summary of a command in the history of an image.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogEntry {
+    /// Version of the image right after the command was applied.
+    pub version: u32,
+    pub kind: CommandKind,
+
+    /// Number of distinct pixels drawn by the command.
+    pub drawn: usize,
+
+    /// Number of distinct pixels erased by the command.
+    pub erased: usize,
+
+    /// Anchors set or removed by the command.
+    pub anchors: Vec<AnchorChange>,
+
+    /// Metadata items put or removed by the command.
+    pub metadata: Vec<MetadataChange>,
+
+    /// Bounding box of the touched pixels and anchors.
+    pub bounds: Option<(Point, Point)>,
+
+    #[serde(skip)]
+    points: BTreeSet<Point>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnchorChange {
+    pub name: String,
+    pub point: Option<Point>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetadataChange {
+    pub name: String,
+    pub removed: bool,
+}
+
+impl LogEntry {
+    pub fn new(version: u32, command: &ImageCommand) -> Self {
+        let mut this = Self {
+            version,
+            kind: CommandKind::of(command),
+            drawn: 0,
+            erased: 0,
+            anchors: Vec::new(),
+            metadata: Vec::new(),
+            bounds: None,
+            points: BTreeSet::new(),
+        };
+        match command {
+            ImageCommand::Patch(patch) => {
+                let mut drawn = BTreeSet::new();
+                let mut erased = BTreeSet::new();
+                for entry in patch.entries() {
+                    // Later entries override earlier ones for the same point.
+                    for &point in &entry.points {
+                        if entry.color.is_some() {
+                            erased.remove(&point);
+                            drawn.insert(point);
+                        } else {
+                            drawn.remove(&point);
+                            erased.insert(point);
+                        }
+                    }
+                }
+                this.drawn = drawn.len();
+                this.erased = erased.len();
+                this.points = drawn.into_iter().chain(erased).collect();
+            }
+            ImageCommand::Anchor { name, point } => {
+                this.anchors.push(AnchorChange {
+                    name: name.clone(),
+                    point: *point,
+                });
+                this.points.extend(*point);
+            }
+            ImageCommand::Put { name, value } => {
+                this.metadata.push(MetadataChange {
+                    name: name.clone(),
+                    removed: value.is_null(),
+                });
+            }
+        }
+        this.bounds = Point::bounding_box(this.points.iter().copied());
+        this
+    }
+
+    /// Returns `true` if any touched pixel or anchor is in the given rectangle (inclusive).
+    pub fn intersects(&self, (start, end): (Point, Point)) -> bool {
+        self.points
+            .range(start..=end)
+            .any(|p| start.x <= p.x && p.x <= end.x)
+    }
+
+    pub fn summary(&self) -> String {
+        match self.kind {
+            CommandKind::Patch => format!("{} drawn, {} erased", self.drawn, self.erased),
+            CommandKind::Anchor => self
+                .anchors
+                .iter()
+                .map(|a| match a.point {
+                    Some(p) => format!("{} = [{},{}]", a.name, p.x, p.y),
+                    None => format!("{} removed", a.name),
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+            CommandKind::Put => self
+                .metadata
+                .iter()
+                .map(|m| {
+                    if m.removed {
+                        format!("{} removed", m.name)
+                    } else {
+                        m.name.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+}
+
+/// Differences between two images.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ImageDiff {
+    pub added: Vec<(Point, Color)>,
+    pub removed: Vec<(Point, Color)>,
+
+    /// Pixels whose colors are changed (point, old color, new color).
+    pub changed: Vec<(Point, Color, Color)>,
+    pub anchors: Vec<Change<Point>>,
+    pub metadata: Vec<Change<serde_json::Value>>,
+}
+
+/// Change of a named item (`None` means that the item does not exist).
+#[derive(Debug, Clone, Serialize)]
+pub struct Change<T> {
+    pub name: String,
+    pub old: Option<T>,
+    pub new: Option<T>,
+}
+
+impl ImageDiff {
+    pub fn new(old: &Image, new: &Image) -> Self {
+        let mut this = Self::default();
+        for (&point, &color) in old.pixels() {
+            match new.get_pixel(point) {
+                None => this.removed.push((point, color)),
+                Some(c) if c != color => this.changed.push((point, color, c)),
+                Some(_) => {}
+            }
+        }
+        for (&point, &color) in new.pixels() {
+            if old.get_pixel(point).is_none() {
+                this.added.push((point, color));
+            }
+        }
+        this.anchors = changes(old.anchors(), new.anchors());
+        this.metadata = changes(old.metadata(), new.metadata());
+        this
+    }
+
+    /// Makes the commands that turn the old image into the new one.
+    pub fn to_commands(&self) -> Vec<ImageCommand> {
+        let mut commands = Vec::new();
+        if !self.removed.is_empty() {
+            let points = self.removed.iter().map(|(p, _)| *p).collect();
+            commands.push(ImageCommand::patch(vec![PatchEntry::erase(points)]));
+        }
+        if !self.added.is_empty() || !self.changed.is_empty() {
+            let pixels = self
+                .added
+                .iter()
+                .copied()
+                .chain(self.changed.iter().map(|&(p, _, c)| (p, c)));
+            commands.push(ImageCommand::draw_pixels(pixels));
+        }
+        for c in &self.anchors {
+            commands.push(ImageCommand::anchor(&c.name, c.new));
+        }
+        for c in &self.metadata {
+            let value = c.new.clone().unwrap_or(serde_json::Value::Null);
+            commands.push(ImageCommand::put(&c.name, value));
+        }
+        commands
+    }
+}
+
+/// Result of a three-way merge.
+#[derive(Debug, Default)]
+pub struct Merge {
+    pub image: Image,
+
+    /// Pixels changed differently on both sides (ours are kept in `image`).
+    pub pixel_conflicts: Vec<Point>,
+
+    /// Anchors changed differently on both sides (ours are kept in `image`).
+    pub anchor_conflicts: Vec<String>,
+
+    /// Metadata items changed differently on both sides (ours are kept in `image`).
+    pub metadata_conflicts: Vec<String>,
+}
+
+impl Merge {
+    /// Merges the changes made in `ours` and `theirs` since `base` pixel by pixel.
+    pub fn new(base: &Image, ours: &Image, theirs: &Image) -> Self {
+        let mut this = Self::default();
+
+        let points = ours
+            .pixels()
+            .keys()
+            .chain(theirs.pixels().keys())
+            .chain(base.pixels().keys())
+            .copied()
+            .collect::<BTreeSet<_>>();
+        let mut pixels = Vec::new();
+        for point in points {
+            let (b, o, t) = (
+                base.get_pixel(point),
+                ours.get_pixel(point),
+                theirs.get_pixel(point),
+            );
+            let (merged, conflict) = merge3(b, o, t);
+            if conflict {
+                this.pixel_conflicts.push(point);
+            }
+            pixels.extend(merged.map(|c| (point, c)));
+        }
+        let mut commands = vec![ImageCommand::draw_pixels(pixels.into_iter())];
+
+        let names = ours
+            .anchors()
+            .keys()
+            .chain(theirs.anchors().keys())
+            .chain(base.anchors().keys())
+            .collect::<BTreeSet<_>>();
+        for name in names {
+            let (merged, conflict) = merge3(
+                base.anchors().get(name),
+                ours.anchors().get(name),
+                theirs.anchors().get(name),
+            );
+            if conflict {
+                this.anchor_conflicts.push(name.clone());
+            }
+            commands.push(ImageCommand::anchor(name, merged.copied()));
+        }
+
+        let names = ours
+            .metadata()
+            .keys()
+            .chain(theirs.metadata().keys())
+            .chain(base.metadata().keys())
+            .collect::<BTreeSet<_>>();
+        for name in names {
+            let (merged, conflict) = merge3(
+                base.metadata().get(name),
+                ours.metadata().get(name),
+                theirs.metadata().get(name),
+            );
+            if conflict {
+                this.metadata_conflicts.push(name.clone());
+            }
+            if let Some(value) = merged {
+                commands.push(ImageCommand::put(name, value.clone()));
+            }
+        }
+
+        for command in &commands {
+            this.image.apply(command);
+        }
+        this
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        !(self.pixel_conflicts.is_empty()
+            && self.anchor_conflicts.is_empty()
+            && self.metadata_conflicts.is_empty())
+    }
+}
+
+/// Merges a value, preferring ours on conflicts.
+fn merge3<T: PartialEq>(base: Option<T>, ours: Option<T>, theirs: Option<T>) -> (Option<T>, bool) {
+    if ours == theirs || theirs == base {
+        (ours, false)
+    } else if ours == base {
+        (theirs, false)
+    } else {
+        (ours, true)
+    }
+}
+
+fn changes<T: Clone + PartialEq>(
+    old: &BTreeMap<String, T>,
+    new: &BTreeMap<String, T>,
+) -> Vec<Change<T>> {
+    let names = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+    names
+        .into_iter()
+        .filter(|name| old.get(*name) != new.get(*name))
+        .map(|name| Change {
+            name: name.clone(),
+            old: old.get(name).cloned(),
+            new: new.get(name).cloned(),
+        })
+        .collect()
+}
+
+/// Formats a color as `#rrggbb` (or `#rrggbbaa` if it is not opaque).
+pub fn color_to_hex(c: Color) -> String {
+    if c.a == 255 {
+        format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b)
+    } else {
+        format!("#{:02x}{:02x}{:02x}{:02x}", c.r, c.g, c.b, c.a)
+    }
+}
+
+/// Makes the commands that bring the given image back to the state at `version`.
+///
+/// Pixels, anchors and metadata are all restored.
+/// Returns `None` if the version is newer than the current one.
+pub fn revert_commands(image: &VersionedImage, version: Version) -> Option<Vec<ImageCommand>> {
+    let old = image.restore_image(version)?;
+    let current = image.restore_image(image.version())?;
+    Some(ImageDiff::new(&current, &old).to_commands())
+}
+
+/// Makes the commands that build the given image from scratch.
+pub fn snapshot_commands(image: &Image) -> Vec<ImageCommand> {
+    let mut commands = Vec::new();
+    if !image.pixels().is_empty() {
+        commands.push(ImageCommand::draw_pixels(
+            image.pixels().iter().map(|(p, c)| (*p, *c)),
+        ));
+    }
+    for (name, point) in image.anchors() {
+        commands.push(ImageCommand::anchor(name, Some(*point)));
+    }
+    for (name, value) in image.metadata() {
+        commands.push(ImageCommand::put(name, value.clone()));
+    }
+    commands
+}
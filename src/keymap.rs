@@ -0,0 +1,183 @@
+use crate::config::{Config, Key, KeyConfig};
+use pagurus::event::{self, KeyEvent};
+use paticanvas::{CanvasCommand, CanvasMode};
+use std::{collections::BTreeMap, time::Duration};
+
+/// Upper limit of count prefixes (to avoid freezing the editor by a mistyped count).
+const MAX_COUNT: u32 = 9999;
+
+/// Key bindings for each mode.
+#[derive(Debug, Default, Clone)]
+pub struct Keymap {
+    key: KeyConfig,
+    mode_key: BTreeMap<CanvasMode, KeyConfig>,
+}
+
+impl Keymap {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            key: config.key.clone(),
+            mode_key: config.mode_key.clone(),
+        }
+    }
+
+    /// Gets the key configs that are active in the given mode (in order of precedence).
+    fn active(&self, mode: CanvasMode) -> impl '_ + Iterator<Item = &KeyConfig> {
+        self.mode_key.get(&mode).into_iter().chain(Some(&self.key))
+    }
+
+    fn get_commands(&self, mode: CanvasMode, keys: &[Key]) -> Option<&[CanvasCommand]> {
+        self.active(mode).find_map(|c| c.get_commands(keys))
+    }
+
+    fn is_bound(&self, mode: CanvasMode, keys: &[Key]) -> bool {
+        self.active(mode)
+            .any(|c| c.get_commands(keys).is_some() || c.is_prefix(keys))
+    }
+}
+
+/// Result of [`KeyInput::handle_key()`].
+#[derive(Debug)]
+pub enum KeyInputResult<'a> {
+    /// More keys are needed to complete a sequence (or a count prefix).
+    Pending,
+
+    /// A sequence is completed (`commands` should be executed `count` times).
+    Matched {
+        commands: &'a [CanvasCommand],
+        count: u32,
+    },
+
+    /// The input does not match any binding and is discarded.
+    Unbound,
+}
+
+/// Keys typed so far that do not make a complete binding yet.
+///
+/// Digits typed before a sequence make a count prefix (e.g., `8 l`),
+/// unless the digit itself is bound in the current mode.
+/// A pending input is discarded if no key is typed within [`KeyInput::TIMEOUT`].
+#[derive(Debug, Default, Clone)]
+pub struct KeyInput {
+    count: Option<u32>,
+    keys: Vec<Key>,
+    last_input_time: Duration,
+}
+
+impl KeyInput {
+    pub const TIMEOUT: Duration = Duration::from_secs(1);
+
+    pub fn handle_key<'a>(
+        &mut self,
+        keymap: &'a Keymap,
+        mode: CanvasMode,
+        key: KeyEvent,
+        now: Duration,
+    ) -> KeyInputResult<'a> {
+        self.handle_tick(now);
+        self.last_input_time = now;
+
+        let key = Key::new(key);
+        if self.keys.is_empty() && (self.count.is_some() || !keymap.is_bound(mode, &[key])) {
+            if let Some(digit) = count_digit(key.get(), self.count.is_some()) {
+                let count = self.count.unwrap_or(0) * 10 + digit;
+                self.count = Some(count.min(MAX_COUNT));
+                return KeyInputResult::Pending;
+            }
+        }
+
+        self.keys.push(key);
+        if let Some(commands) = keymap.get_commands(mode, &self.keys) {
+            let count = self.count.unwrap_or(1);
+            self.clear();
+            KeyInputResult::Matched { commands, count }
+        } else if keymap.is_bound(mode, &self.keys) {
+            KeyInputResult::Pending
+        } else {
+            self.clear();
+            KeyInputResult::Unbound
+        }
+    }
+
+    /// Discards the pending input if it has timed out.
+    pub fn handle_tick(&mut self, now: Duration) {
+        if now >= self.last_input_time + Self::TIMEOUT {
+            self.clear();
+        }
+    }
+
+    /// Gets the text representing the pending input (e.g., `"8 g"`) if any.
+    pub fn pending_text(&self) -> Option<String> {
+        let text = self
+            .count
+            .map(|c| c.to_string())
+            .into_iter()
+            .chain(self.keys.iter().map(|k| k.to_string()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        (!text.is_empty()).then_some(text)
+    }
+
+    fn clear(&mut self) {
+        self.count = None;
+        self.keys.clear();
+    }
+}
+
+fn count_digit(key: KeyEvent, continued: bool) -> Option<u32> {
+    if key.ctrl || key.alt {
+        return None;
+    }
+    let event::Key::Char(c) = key.key else {
+        return None;
+    };
+    let digit = c.to_digit(10)?;
+    (digit != 0 || continued).then_some(digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences_and_counts() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "key": {"l": ["draw"], "g g": ["pick"], "1": ["quit"]},
+                "mode_key": {"marking": {"l": ["erase"]}}
+            }"#,
+        )
+        .unwrap();
+        let keymap = Keymap::new(&config);
+        let mut input = KeyInput::default();
+        let mut type_keys = |keys: &str, mode, now| {
+            let mut result = String::new();
+            for c in keys.chars() {
+                let key = KeyEvent::from(event::Key::Char(c));
+                result = match input.handle_key(&keymap, mode, key, now) {
+                    KeyInputResult::Pending => "pending".to_owned(),
+                    KeyInputResult::Matched { commands, count } => {
+                        format!("{}x{count}", serde_json::to_string(commands).unwrap())
+                    }
+                    KeyInputResult::Unbound => "unbound".to_owned(),
+                };
+            }
+            result
+        };
+        let neutral = CanvasMode::Neutral;
+        let ms = Duration::from_millis;
+
+        assert_eq!(type_keys("l", neutral, ms(0)), r#"["draw"]x1"#);
+        assert_eq!(type_keys("20l", neutral, ms(0)), r#"["draw"]x20"#);
+        assert_eq!(type_keys("gg", neutral, ms(0)), r#"["pick"]x1"#);
+        assert_eq!(type_keys("3g", neutral, ms(0)), "pending");
+        assert_eq!(type_keys("g", neutral, ms(500)), r#"["pick"]x3"#);
+        assert_eq!(type_keys("gx", neutral, ms(0)), "unbound");
+        assert_eq!(type_keys("1", neutral, ms(0)), r#"["quit"]x1"#);
+        assert_eq!(type_keys("l", CanvasMode::Marking, ms(0)), r#"["erase"]x1"#);
+
+        // Timeout.
+        assert_eq!(type_keys("5g", neutral, ms(0)), "pending");
+        assert_eq!(type_keys("g", neutral, ms(2000)), "pending");
+    }
+}
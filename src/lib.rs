@@ -1,14 +1,24 @@
-// pub mod bmp;
+pub mod ansi;
+pub mod bmp;
 pub mod cli;
 // pub mod clock; // TODO: rename module
 // pub mod command;
 pub mod config;
-// pub mod editor;
 // pub mod frame;
+pub mod font;
 pub mod game;
-// pub mod marker;
+pub mod history;
+pub mod keymap;
+pub mod minibuffer;
 pub mod model;
+pub mod palette;
+pub mod palette_panel;
+pub mod png;
 // pub mod query;
 // pub mod remote;
 pub mod screen;
+pub mod status_line;
+pub mod svg;
+pub mod template;
+pub mod timelapse;
 pub mod view;
@@ -0,0 +1,316 @@
+use orfail::{Failure, OrFail};
+use pagurus::event::{Key, KeyEvent};
+use pati::Color;
+use paticanvas::{Canvas, CanvasCommand};
+
+const COMMAND_NAMES: &[&str] = &[
+    "anchor", "cancel", "center", "copy", "cut", "dip", "draw", "embed", "erase", "flip", "image",
+    "import", "jump", "mark", "move", "pick", "play", "quit", "remove", "rotate", "scale", "tick",
+];
+const MARK_KINDS: &[&str] = &[
+    "all",
+    "color",
+    "ellipse",
+    "fill",
+    "line",
+    "rectangle",
+    "region",
+    "stroke",
+];
+const FLIP_DIRECTIONS: &[&str] = &["horizontal", "vertical"];
+const REMOVE_TARGETS: &[&str] = &["anchor", "frame"];
+
+/// Prompt to type a command in the editor.
+#[derive(Debug, Default)]
+pub struct Minibuffer {
+    active: bool,
+    input: String,
+    error: Option<String>,
+    history: Vec<String>,
+
+    /// Position in `history` while browsing it with Up/Down.
+    history_index: Option<usize>,
+}
+
+/// Result of [`Minibuffer::handle_key()`].
+#[derive(Debug)]
+pub enum MinibufferAction {
+    None,
+    Execute(CanvasCommand),
+}
+
+impl Minibuffer {
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn open(&mut self) {
+        self.active = true;
+        self.input.clear();
+        self.error = None;
+        self.history_index = None;
+    }
+
+    pub fn close(&mut self) {
+        self.active = false;
+        self.error = None;
+    }
+
+    /// Records the result of executing the command returned by [`Minibuffer::handle_key()`].
+    ///
+    /// On failure, the prompt is kept open with the error message so that the input can be fixed.
+    pub fn set_result(&mut self, result: orfail::Result<()>) {
+        match result {
+            Ok(()) => self.close(),
+            Err(e) => self.error = Some(e.message),
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent, canvas: &Canvas) -> MinibufferAction {
+        match (key.ctrl, key.alt, key.key) {
+            (false, false, Key::Esc) | (true, false, Key::Char('g')) => self.close(),
+            (false, false, Key::Return) => return self.submit(),
+            (false, false, Key::Backspace) if self.input.pop().is_none() => self.close(),
+            (true, false, Key::Char('u')) => self.input.clear(),
+            (false, false, Key::Tab) => self.complete(canvas),
+            (false, false, Key::Up) | (true, false, Key::Char('p')) => self.browse_history(-1),
+            (false, false, Key::Down) | (true, false, Key::Char('n')) => self.browse_history(1),
+            (false, false, Key::Char(c)) => self.input.push(c),
+            _ => {}
+        }
+        MinibufferAction::None
+    }
+
+    fn submit(&mut self) -> MinibufferAction {
+        let input = self.input.trim().to_owned();
+        if input.is_empty() {
+            self.close();
+            return MinibufferAction::None;
+        }
+        if self.history.last() != Some(&input) {
+            self.history.push(input.clone());
+        }
+        self.history_index = None;
+        match parse_command(&input) {
+            Ok(command) => MinibufferAction::Execute(command),
+            Err(e) => {
+                self.error = Some(e.message);
+                MinibufferAction::None
+            }
+        }
+    }
+
+    fn browse_history(&mut self, delta: isize) {
+        if self.history.is_empty() {
+            return;
+        }
+        let last = self.history.len() - 1;
+        let index = match (self.history_index, delta < 0) {
+            (None, true) => Some(last),
+            (None, false) => None,
+            (Some(0), true) => Some(0),
+            (Some(i), true) => Some(i - 1),
+            (Some(i), false) if i == last => None,
+            (Some(i), false) => Some(i + 1),
+        };
+        self.history_index = index;
+        self.input = index.map(|i| self.history[i].clone()).unwrap_or_default();
+    }
+
+    /// Completes the last word with command names, mark kinds, flip directions or anchor names.
+    fn complete(&mut self, canvas: &Canvas) {
+        // Words are separated in the same way as `parse_command()`.
+        let word = self
+            .input
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or_default();
+        let words = self.input[..self.input.len() - word.len()]
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        let anchors = canvas.image().anchors().keys().map(|s| s.as_str());
+        let candidates: Vec<&str> = match words.as_slice() {
+            [] => COMMAND_NAMES.to_vec(),
+            ["mark"] => MARK_KINDS.to_vec(),
+            ["flip"] => FLIP_DIRECTIONS.to_vec(),
+            ["remove"] => REMOVE_TARGETS.to_vec(),
+            ["anchor"] | ["jump"] | ["remove", "anchor"] => anchors.collect(),
+            ["remove", "frame"] => canvas.frames().keys().map(|s| s.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        let candidates = candidates
+            .into_iter()
+            .filter(|c| c.starts_with(word))
+            .collect::<Vec<_>>();
+        let Some(first) = candidates.first() else {
+            return;
+        };
+        let common_len = candidates.iter().fold(first.len(), |len, c| {
+            first
+                .chars()
+                .zip(c.chars())
+                .take_while(|(a, b)| a == b)
+                .count()
+                .min(len)
+        });
+        let completed = first.chars().take(common_len).collect::<String>();
+
+        self.input.truncate(self.input.len() - word.len());
+        self.input.push_str(&completed);
+        if candidates.len() == 1 {
+            self.input.push(' ');
+        }
+    }
+}
+
+/// Parses a command in JSON (e.g., `{"move": [3, -2]}`) or in the short syntax (e.g., `move 3 -2`).
+///
+/// In the short syntax, a command name is followed by its arguments separated by spaces.
+/// Each argument is a JSON value or a bare word (taken as a string),
+/// and multiple arguments make an array.
+/// In addition, the following forms are supported:
+///
+/// - `dip #rrggbb` (or `#rrggbbaa`)
+/// - `remove anchor NAME` / `remove frame NAME`
+pub fn parse_command(text: &str) -> orfail::Result<CanvasCommand> {
+    let text = text.trim();
+    if text.starts_with('{') || text.starts_with('"') {
+        return serde_json::from_str(text).or_fail_with(|e| e.to_string());
+    }
+
+    let mut words = text.split_whitespace();
+    let name = words.next().or_fail_with(|()| "Empty command".to_owned())?;
+    let args = words.collect::<Vec<_>>();
+    match (name, args.as_slice()) {
+        ("dip", [hex]) if hex.starts_with('#') => {
+            return parse_hex_color(hex).map(CanvasCommand::Dip);
+        }
+        ("remove", [target, name]) => {
+            let json = serde_json::json!({"remove": {*target: name}});
+            return serde_json::from_value(json).or_fail_with(|e| e.to_string());
+        }
+        _ => {}
+    }
+
+    let json = if args.is_empty() {
+        serde_json::Value::String(name.to_owned())
+    } else {
+        let mut values = args
+            .iter()
+            .map(|arg| {
+                serde_json::from_str(arg)
+                    .unwrap_or_else(|_| serde_json::Value::String((*arg).to_owned()))
+            })
+            .collect::<Vec<_>>();
+        let value = if values.len() == 1 {
+            values.remove(0)
+        } else {
+            serde_json::Value::Array(values)
+        };
+        serde_json::json!({ name: value })
+    };
+    serde_json::from_value(json).or_fail_with(|e| e.to_string())
+}
+
+fn parse_hex_color(s: &str) -> orfail::Result<Color> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let invalid = || Failure::new(format!("Invalid color: {s:?}"));
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+    Ok(Color {
+        r: component(0)?,
+        g: component(2)?,
+        b: component(4)?,
+        a: if hex.len() == 8 { component(6)? } else { 255 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pati::ImageCommand;
+
+    #[test]
+    fn parse_short_syntax() {
+        let parse = |s: &str| {
+            parse_command(s)
+                .map(|c| serde_json::to_string(&c).unwrap())
+                .map_err(|e| e.message)
+        };
+
+        assert_eq!(parse("move 3 -2").unwrap(), r#"{"move":[3,-2]}"#);
+        assert_eq!(parse(r#"{"move": [3, -2]}"#).unwrap(), r#"{"move":[3,-2]}"#);
+        assert_eq!(parse("draw").unwrap(), r#""draw""#);
+        assert_eq!(parse("mark rectangle").unwrap(), r#"{"mark":"rectangle"}"#);
+        assert_eq!(parse("dip #ff000080").unwrap(), r#"{"dip":[255,0,0,128]}"#);
+        assert_eq!(parse("dip 1 2 3").unwrap(), r#"{"dip":[1,2,3]}"#);
+        assert_eq!(parse("anchor head").unwrap(), r#"{"anchor":"head"}"#);
+        assert_eq!(parse("center").unwrap(), r#""center""#);
+        assert_eq!(
+            parse("remove anchor head").unwrap(),
+            r#"{"remove":{"anchor":"head"}}"#
+        );
+        assert!(parse("dip #ff00").is_err());
+        assert!(parse("jump 1").is_err());
+        assert!(parse("teleport origin").is_err());
+    }
+
+    #[test]
+    fn completion_and_history() {
+        let mut canvas = Canvas::new();
+        let command = ImageCommand::anchor("head", Some(pati::Point::ORIGIN));
+        canvas.command(&CanvasCommand::Image(command)).unwrap();
+        let mut minibuffer = Minibuffer::default();
+        let type_keys = |minibuffer: &mut Minibuffer, keys: &[Key]| {
+            for &key in keys {
+                minibuffer.handle_key(KeyEvent::from(key), &canvas);
+            }
+        };
+
+        minibuffer.open();
+        type_keys(&mut minibuffer, &[Key::Char('c'), Key::Tab]);
+        assert_eq!(minibuffer.input(), "c");
+        type_keys(&mut minibuffer, &[Key::Char('u'), Key::Tab]);
+        assert_eq!(minibuffer.input(), "cut ");
+
+        minibuffer.open();
+        type_keys(&mut minibuffer, &[Key::Char('e'), Key::Char('m'), Key::Tab]);
+        assert_eq!(minibuffer.input(), "embed ");
+
+        // Extra spaces are ignored as in `parse_command()`.
+        minibuffer.open();
+        for c in " mark  rec".chars() {
+            type_keys(&mut minibuffer, &[Key::Char(c)]);
+        }
+        type_keys(&mut minibuffer, &[Key::Tab]);
+        assert_eq!(minibuffer.input(), " mark  rectangle ");
+
+        minibuffer.open();
+        type_keys(
+            &mut minibuffer,
+            &[Key::Char('a'), Key::Tab, Key::Char('h'), Key::Tab],
+        );
+        assert_eq!(minibuffer.input(), "anchor head ");
+        type_keys(&mut minibuffer, &[Key::Return]);
+
+        minibuffer.open();
+        type_keys(&mut minibuffer, &[Key::Char('x'), Key::Return]);
+        assert!(minibuffer.error().is_some());
+        type_keys(&mut minibuffer, &[Key::Up, Key::Up]);
+        assert_eq!(minibuffer.input(), "anchor head");
+        type_keys(&mut minibuffer, &[Key::Down]);
+        assert_eq!(minibuffer.input(), "x");
+        type_keys(&mut minibuffer, &[Key::Down]);
+        assert_eq!(minibuffer.input(), "");
+    }
+}
@@ -1,5 +1,5 @@
 use orfail::OrFail;
-use paticanvas::{CanvasCommand, CanvasFile, CanvasQuery, CanvasQueryValue};
+use paticanvas::{Canvas, CanvasCommand, CanvasFile, CanvasQuery, CanvasQueryValue};
 use std::num::NonZeroU8;
 
 #[derive(Debug)]
@@ -10,6 +10,10 @@ impl Model {
         Self(canvas_file)
     }
 
+    pub fn canvas(&self) -> &Canvas {
+        self.0.canvas()
+    }
+
     pub fn command(&mut self, command: &CanvasCommand) -> orfail::Result<()> {
         self.0.command(command).or_fail()
     }
@@ -18,6 +22,10 @@ impl Model {
         self.0.canvas().query(query)
     }
 
+    pub fn unsaved_commands(&self) -> usize {
+        self.0.unsaved_commands()
+    }
+
     pub fn sync(&mut self) -> orfail::Result<()> {
         self.0.sync().or_fail()
     }
@@ -0,0 +1,443 @@
+use orfail::OrFail;
+use pati::{Color, Image, ImageCommand, Point};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{BufRead, Write},
+    path::Path,
+};
+
+pub const ANCHOR_PALETTE_START: &str = "palette.start";
+pub const ANCHOR_PALETTE_END: &str = "palette.end";
+pub const METADATA_PALETTE_NAME: &str = "patica.palette.name";
+pub const METADATA_PALETTE_GRID: &str = "patica.palette";
+
+const DEFAULT_COLUMNS: usize = 16;
+const GRID_BACKGROUND_COLOR: Color = Color::rgb(164, 163, 156);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+    /// GIMP palette (`.gpl`).
+    Gpl,
+
+    /// JASC palette (`.pal`).
+    JascPal,
+
+    /// Lospec hex palette (`.hex`).
+    Hex,
+}
+
+impl PaletteFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> orfail::Result<Self> {
+        let path = path.as_ref();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gpl") => Ok(Self::Gpl),
+            Some("pal") => Ok(Self::JascPal),
+            Some("hex") => Ok(Self::Hex),
+            _ => Err(orfail::Failure::new(format!(
+                "Unknown palette format: {} (expected .gpl, .pal or .hex)",
+                path.display()
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Palette {
+    pub name: Option<String>,
+    pub columns: Option<usize>,
+    pub entries: Vec<PaletteEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteEntry {
+    pub color: Color,
+    pub name: Option<String>,
+}
+
+impl PaletteEntry {
+    pub const fn new(color: Color) -> Self {
+        Self { color, name: None }
+    }
+}
+
+impl Palette {
+    pub fn read<R: BufRead>(reader: R, format: PaletteFormat) -> orfail::Result<Self> {
+        let lines = reader.lines().collect::<Result<Vec<_>, _>>().or_fail()?;
+        match format {
+            PaletteFormat::Gpl => Self::parse_gpl(&lines).or_fail(),
+            PaletteFormat::JascPal => Self::parse_jasc_pal(&lines).or_fail(),
+            PaletteFormat::Hex => Self::parse_hex(&lines).or_fail(),
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W, format: PaletteFormat) -> orfail::Result<()> {
+        match format {
+            PaletteFormat::Gpl => {
+                writeln!(writer, "GIMP Palette").or_fail()?;
+                if let Some(name) = &self.name {
+                    writeln!(writer, "Name: {name}").or_fail()?;
+                }
+                if let Some(columns) = self.columns {
+                    writeln!(writer, "Columns: {columns}").or_fail()?;
+                }
+                writeln!(writer, "#").or_fail()?;
+                for entry in &self.entries {
+                    let c = entry.color;
+                    write!(writer, "{:3} {:3} {:3}", c.r, c.g, c.b).or_fail()?;
+                    if let Some(name) = &entry.name {
+                        write!(writer, "\t{name}").or_fail()?;
+                    }
+                    writeln!(writer).or_fail()?;
+                }
+            }
+            PaletteFormat::JascPal => {
+                writeln!(writer, "JASC-PAL\r\n0100\r\n{}\r", self.entries.len()).or_fail()?;
+                for entry in &self.entries {
+                    let c = entry.color;
+                    writeln!(writer, "{} {} {}\r", c.r, c.g, c.b).or_fail()?;
+                }
+            }
+            PaletteFormat::Hex => {
+                for entry in &self.entries {
+                    let c = entry.color;
+                    writeln!(writer, "{:02x}{:02x}{:02x}", c.r, c.g, c.b).or_fail()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Extracts a palette from an image laid out as a palette grid.
+    ///
+    /// The grid starts at the `palette.start` anchor (or the top-left corner of the image if
+    /// the anchors are missing).
+    /// If the grid was drawn by [`Palette::to_image_commands()`], the swatches are read
+    /// from the positions recorded in the `patica.palette` metadata.
+    /// Otherwise, the distinct colors up to `palette.end` are collected in row-major order,
+    /// and the color at the top-left corner is regarded as the grid background and is skipped.
+    pub fn from_image(image: &Image) -> orfail::Result<Self> {
+        let anchors = image.anchors();
+        let (start, end) = match (
+            anchors.get(ANCHOR_PALETTE_START),
+            anchors.get(ANCHOR_PALETTE_END),
+        ) {
+            (Some(&start), Some(&end)) => (start, end),
+            _ => image
+                .bounding_box()
+                .or_fail_with(|()| "Empty image".to_owned())?,
+        };
+        let layout = image
+            .metadata()
+            .get(METADATA_PALETTE_GRID)
+            .map(|v| serde_json::from_value::<GridLayout>(v.clone()))
+            .transpose()
+            .or_fail()?;
+
+        let mut entries = Vec::new();
+        if let Some(layout) = layout {
+            for i in 0..layout.colors {
+                let point = layout.swatch(start, i).or_fail()?;
+                if let Some(color) = image.get_pixel(point) {
+                    entries.push(PaletteEntry::new(color));
+                }
+            }
+        } else {
+            let background = image.get_pixel(start);
+            let mut seen = HashSet::new();
+            for (_, color) in image.range_pixels(start..=end) {
+                if Some(color) != background && seen.insert(color) {
+                    entries.push(PaletteEntry::new(color));
+                }
+            }
+        }
+
+        let name = image
+            .metadata()
+            .get(METADATA_PALETTE_NAME)
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_owned());
+        Ok(Self {
+            name,
+            columns: layout.map(|l| l.columns),
+            entries,
+        })
+    }
+
+    /// Loads a palette from a palette file (`.gpl`, `.pal` or `.hex`) or a palette grid image (`.pati`).
+    pub fn load<P: AsRef<Path>>(path: P) -> orfail::Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .or_fail_with(|e| format!("Failed to open file {}: {e}", path.display()))?;
+        let reader = std::io::BufReader::new(file);
+        if path.extension().is_some_and(|ext| ext == "pati") {
+            let mut reader = pati::ImageCommandReader::new(reader);
+            let mut image = Image::new();
+            while let Some(command) = reader.read_command().or_fail()? {
+                image.apply(&command);
+            }
+            Self::from_image(&image).or_fail()
+        } else {
+            let format = PaletteFormat::from_path(path).or_fail()?;
+            Self::read(reader, format).or_fail()
+        }
+    }
+
+    /// Makes the commands to draw this palette as a palette grid whose top-left corner is at `top_left`.
+    ///
+    /// The layout follows `examples/gen_copic_palette.rs`: swatches are surrounded by
+    /// a one pixel border, and `palette.start`, `palette.end` and `origin` anchors are set.
+    /// The layout is recorded in the `patica.palette` metadata so that the swatches can be
+    /// told apart from the border by [`Palette::from_image()`].
+    pub fn to_image_commands(&self, top_left: Point) -> orfail::Result<Vec<ImageCommand>> {
+        let columns = self
+            .columns
+            .filter(|&n| n > 0)
+            .unwrap_or(DEFAULT_COLUMNS)
+            .min(self.entries.len().max(1));
+        let layout = GridLayout {
+            columns,
+            colors: self.entries.len(),
+        };
+        let width = columns + 2;
+        let height = layout.colors.div_ceil(columns).max(1) + 2;
+        let end = layout.point(top_left, width - 1, height - 1).or_fail()?;
+
+        let mut pixels = BTreeMap::new();
+        for y in 0..height {
+            for x in 0..width {
+                let point = layout.point(top_left, x, y).or_fail()?;
+                pixels.insert(point, GRID_BACKGROUND_COLOR);
+            }
+        }
+        for (i, entry) in self.entries.iter().enumerate() {
+            pixels.insert(layout.swatch(top_left, i).or_fail()?, entry.color);
+        }
+
+        let mut commands = vec![
+            ImageCommand::draw_pixels(pixels.into_iter()),
+            ImageCommand::anchor(ANCHOR_PALETTE_START, Some(top_left)),
+            ImageCommand::anchor(ANCHOR_PALETTE_END, Some(end)),
+            ImageCommand::anchor(
+                "origin",
+                Some(layout.point(top_left, width / 2, height / 2).or_fail()?),
+            ),
+            ImageCommand::put(
+                METADATA_PALETTE_GRID,
+                serde_json::to_value(layout).or_fail()?,
+            ),
+        ];
+        if let Some(name) = &self.name {
+            commands.push(ImageCommand::put(
+                METADATA_PALETTE_NAME,
+                serde_json::Value::String(name.clone()),
+            ));
+        }
+        Ok(commands)
+    }
+
+    fn parse_gpl(lines: &[String]) -> orfail::Result<Self> {
+        let mut lines = lines.iter();
+        let header = lines.next().map(|s| s.trim());
+        (header == Some("GIMP Palette"))
+            .or_fail_with(|()| "Missing 'GIMP Palette' header".to_owned())?;
+
+        let mut palette = Self::default();
+        for line in lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix("Name:") {
+                palette.name = Some(name.trim().to_owned());
+                continue;
+            }
+            if let Some(columns) = line.strip_prefix("Columns:") {
+                palette.columns = Some(
+                    columns
+                        .trim()
+                        .parse::<usize>()
+                        .or_fail_with(|e| format!("Invalid columns {columns:?}: {e}"))?,
+                );
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+            let mut component = || -> orfail::Result<u8> {
+                let token = tokens
+                    .next()
+                    .or_fail_with(|()| format!("Invalid color line: {line:?}"))?;
+                token
+                    .parse::<u8>()
+                    .or_fail_with(|e| format!("Invalid color component {token:?}: {e}"))
+            };
+            let color = Color::rgb(component()?, component()?, component()?);
+            let name = tokens.collect::<Vec<_>>().join(" ");
+            palette.entries.push(PaletteEntry {
+                color,
+                name: (!name.is_empty()).then_some(name),
+            });
+        }
+        Ok(palette)
+    }
+
+    fn parse_jasc_pal(lines: &[String]) -> orfail::Result<Self> {
+        let mut lines = lines.iter().map(|s| s.trim());
+        (lines.next() == Some("JASC-PAL"))
+            .or_fail_with(|()| "Missing 'JASC-PAL' header".to_owned())?;
+        let _version = lines.next().or_fail()?;
+        let count = lines
+            .next()
+            .or_fail()?
+            .parse::<usize>()
+            .or_fail_with(|e| format!("Invalid color count: {e}"))?;
+
+        let mut palette = Self::default();
+        for line in lines.filter(|line| !line.is_empty()).take(count) {
+            let components = line
+                .split_whitespace()
+                .map(|s| s.parse::<u8>())
+                .collect::<Result<Vec<_>, _>>()
+                .or_fail_with(|e| format!("Invalid color line {line:?}: {e}"))?;
+            let &[r, g, b] = components.as_slice() else {
+                return Err(orfail::Failure::new(format!(
+                    "Invalid color line: {line:?}"
+                )));
+            };
+            palette.entries.push(PaletteEntry::new(Color::rgb(r, g, b)));
+        }
+        (palette.entries.len() == count).or_fail_with(|()| {
+            format!(
+                "Expected {count} colors, but found {}",
+                palette.entries.len()
+            )
+        })?;
+        Ok(palette)
+    }
+
+    fn parse_hex(lines: &[String]) -> orfail::Result<Self> {
+        let mut palette = Self::default();
+        for line in lines {
+            let hex = line.trim().trim_start_matches('#');
+            if hex.is_empty() {
+                continue;
+            }
+            (hex.len() == 6).or_fail_with(|()| format!("Invalid hex color: {line:?}"))?;
+            let rgb = u32::from_str_radix(hex, 16)
+                .or_fail_with(|e| format!("Invalid hex color {line:?}: {e}"))?;
+            palette.entries.push(PaletteEntry::new(Color::rgb(
+                (rgb >> 16) as u8,
+                (rgb >> 8) as u8,
+                rgb as u8,
+            )));
+        }
+        Ok(palette)
+    }
+}
+
+/// Layout of a palette grid drawn by [`Palette::to_image_commands()`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct GridLayout {
+    /// Number of swatches per row.
+    columns: usize,
+
+    /// Number of swatches.
+    colors: usize,
+}
+
+impl GridLayout {
+    /// Gets the point of the `i`-th swatch in the grid whose top-left corner is at `top_left`.
+    fn swatch(self, top_left: Point, i: usize) -> orfail::Result<Point> {
+        (self.columns > 0).or_fail_with(|()| "Invalid palette grid: no columns".to_owned())?;
+        self.point(top_left, i % self.columns + 1, i / self.columns + 1)
+    }
+
+    /// Gets the point at (`x`, `y`) relative to `top_left`.
+    fn point(self, top_left: Point, x: usize, y: usize) -> orfail::Result<Point> {
+        let coordinate = |start: i16, offset: usize| {
+            i32::try_from(offset)
+                .ok()
+                .and_then(|offset| i16::try_from(start as i32 + offset).ok())
+                .or_fail_with(|()| {
+                    format!(
+                        "Too large palette grid: {} colors in {} columns",
+                        self.colors, self.columns
+                    )
+                })
+        };
+        Ok(Point::new(
+            coordinate(top_left.x, x).or_fail()?,
+            coordinate(top_left.y, y).or_fail()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_formats_roundtrip() {
+        let palette = Palette {
+            name: None,
+            columns: None,
+            entries: vec![
+                PaletteEntry::new(Color::rgb(0, 0, 0)),
+                PaletteEntry::new(Color::rgb(255, 128, 1)),
+            ],
+        };
+        for format in [
+            PaletteFormat::Gpl,
+            PaletteFormat::JascPal,
+            PaletteFormat::Hex,
+        ] {
+            let mut buf = Vec::new();
+            palette.write(&mut buf, format).unwrap();
+            assert_eq!(Palette::read(&buf[..], format).unwrap(), palette);
+        }
+    }
+
+    #[test]
+    fn palette_grid_roundtrip() {
+        // The border color and duplicates are kept as swatches.
+        let palette = Palette {
+            name: Some("test".to_owned()),
+            columns: Some(2),
+            entries: [
+                GRID_BACKGROUND_COLOR,
+                Color::rgb(0, 0, 0),
+                GRID_BACKGROUND_COLOR,
+            ]
+            .into_iter()
+            .map(PaletteEntry::new)
+            .collect(),
+        };
+        let mut image = Image::new();
+        for command in palette.to_image_commands(Point::new(-3, 5)).unwrap() {
+            image.apply(&command);
+        }
+        assert_eq!(Palette::from_image(&image).unwrap(), palette);
+        assert_eq!(
+            image.anchors().get(ANCHOR_PALETTE_END),
+            Some(&Point::new(0, 8))
+        );
+    }
+
+    #[test]
+    fn too_large_palette_grid() {
+        let palette = Palette {
+            name: None,
+            columns: Some(10),
+            entries: vec![PaletteEntry::new(Color::rgb(0, 0, 0)); 10],
+        };
+        assert!(palette.to_image_commands(Point::new(32760, 0)).is_err());
+        assert!(palette.to_image_commands(Point::new(32755, 0)).is_ok());
+
+        let palette = Palette {
+            columns: Some(40000),
+            entries: vec![PaletteEntry::new(Color::rgb(0, 0, 0)); 40000],
+            ..palette
+        };
+        assert!(palette.to_image_commands(Point::ORIGIN).is_err());
+    }
+}
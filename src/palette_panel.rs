@@ -0,0 +1,284 @@
+use crate::{
+    config::{Dock, Key, PaletteAction, PaletteConfig},
+    palette::Palette,
+    screen::Screen,
+};
+use pagurus::{
+    event::KeyEvent,
+    spatial::{Contains, Position, Region, Size},
+};
+use pati::Color;
+use std::collections::BTreeMap;
+
+const DEFAULT_COLUMNS: usize = 8;
+const MAX_RECENT_COLORS: usize = 8;
+const MARGIN: u32 = 1;
+
+/// Size of a swatch including the frame that highlights the selection.
+const CELL_SIZE: u32 = 5;
+
+const SWATCH_SIZE: u32 = 3;
+const BACKGROUND_COLOR: Color = Color::rgb(64, 64, 64);
+const SELECTION_COLOR: Color = Color::rgb(255, 255, 255);
+
+/// Panel showing the colors of a palette (and the recently used colors) next to the canvas.
+///
+/// The recently used colors are shown in the first row and the palette colors follow it
+/// (scrolled so that the selected color is visible).
+#[derive(Debug, Clone)]
+pub struct PalettePanel {
+    colors: Vec<Color>,
+    columns: usize,
+    dock: Dock,
+    key: BTreeMap<Key, PaletteAction>,
+    visible: bool,
+    selected: usize,
+    recent: Vec<Color>,
+    last_brush_color: Option<Color>,
+}
+
+impl PalettePanel {
+    pub fn new(palette: &Palette, config: &PaletteConfig) -> Self {
+        let columns = palette.columns.unwrap_or(DEFAULT_COLUMNS).max(1);
+        Self {
+            colors: palette.entries.iter().map(|e| e.color).collect(),
+            columns,
+            dock: config.dock.unwrap_or_default(),
+            key: config.key.clone(),
+            visible: true,
+            selected: 0,
+            recent: Vec::new(),
+            last_brush_color: None,
+        }
+    }
+
+    pub fn selected_color(&self) -> Option<Color> {
+        self.colors.get(self.selected).copied()
+    }
+
+    /// Gets the recently used colors (the most recent one comes first).
+    pub fn recent_colors(&self) -> &[Color] {
+        &self.recent
+    }
+
+    pub fn get_action(&self, key: KeyEvent) -> Option<PaletteAction> {
+        let action = self.key.get(&Key::new(key)).copied()?;
+        let enabled = match action {
+            PaletteAction::None => false,
+            PaletteAction::Toggle => true,
+            _ => self.visible,
+        };
+        enabled.then_some(action)
+    }
+
+    /// Handles an action and returns the color to be set as the brush color if any.
+    pub fn handle_action(&mut self, action: PaletteAction) -> Option<Color> {
+        let last = self.colors.len().saturating_sub(1);
+        match action {
+            PaletteAction::Left => self.selected = self.selected.saturating_sub(1),
+            PaletteAction::Right => self.selected = (self.selected + 1).min(last),
+            PaletteAction::Up => self.selected = self.selected.saturating_sub(self.columns),
+            PaletteAction::Down => {
+                if self.selected + self.columns <= last {
+                    self.selected += self.columns;
+                }
+            }
+            PaletteAction::Select => return self.selected_color(),
+            PaletteAction::Recent(n) => return self.recent.get(n.checked_sub(1)?).copied(),
+            PaletteAction::Toggle => self.visible = !self.visible,
+            PaletteAction::None => {}
+        }
+        None
+    }
+
+    /// Records the brush color in the recently used colors if it has been changed.
+    pub fn track_brush_color(&mut self, color: Color) {
+        if self
+            .last_brush_color
+            .replace(color)
+            .is_none_or(|c| c == color)
+        {
+            return;
+        }
+        self.recent.retain(|&c| c != color);
+        self.recent.insert(0, color);
+        self.recent.truncate(MAX_RECENT_COLORS);
+    }
+
+    /// Gets the region of the panel in `area` (`None` if the panel is hidden).
+    pub fn region(&self, area: Region) -> Option<Region> {
+        if !self.visible {
+            return None;
+        }
+        let width = (self.columns as u32 * CELL_SIZE + MARGIN * 2).min(area.size.width);
+        let x = match self.dock {
+            Dock::Left => area.position.x,
+            Dock::Right => area.end().x - width as i32,
+        };
+        let position = Position::from_xy(x, area.position.y);
+        Some(Region::new(
+            position,
+            Size::from_wh(width, area.size.height),
+        ))
+    }
+
+    /// Gets the part of `area` that is not covered by the panel.
+    pub fn remaining_area(&self, area: Region) -> Region {
+        let Some(panel) = self.region(area) else {
+            return area;
+        };
+        let size = Size::from_wh(area.size.width - panel.size.width, area.size.height);
+        let x = match self.dock {
+            Dock::Left => panel.end().x,
+            Dock::Right => area.position.x,
+        };
+        Region::new(Position::from_xy(x, area.position.y), size)
+    }
+
+    /// Handles a click in the panel and returns the clicked color if any.
+    pub fn handle_click(&mut self, area: Region, position: Position) -> Option<Color> {
+        let (_, index) = self
+            .cells(area)
+            .find(|(cell, _)| cell.contains(&position))?;
+        match index {
+            Cell::Recent(i) => self.recent.get(i).copied(),
+            Cell::Palette(i) => {
+                self.selected = i;
+                self.selected_color()
+            }
+        }
+    }
+
+    pub fn render(&self, area: Region, screen: &mut Screen) {
+        let Some(panel) = self.region(area) else {
+            return;
+        };
+        screen.fill_region(panel, BACKGROUND_COLOR);
+        for (cell, index) in self.cells(area) {
+            let color = match index {
+                Cell::Recent(i) => self.recent[i],
+                Cell::Palette(i) => self.colors[i],
+            };
+            if index == Cell::Palette(self.selected) {
+                screen.fill_region(cell, SELECTION_COLOR);
+            }
+            let offset = ((CELL_SIZE - SWATCH_SIZE) / 2) as i32;
+            let position = cell.position + Position::from_xy(offset, offset);
+            screen.fill_region(Region::new(position, Size::square(SWATCH_SIZE)), color);
+        }
+    }
+
+    /// Gets the visible cells and their regions on the screen.
+    fn cells(&self, area: Region) -> impl '_ + Iterator<Item = (Region, Cell)> {
+        let panel = self.region(area).unwrap_or_default();
+        let rows = (panel.size.height.saturating_sub(MARGIN * 2) / CELL_SIZE) as usize;
+        let palette_rows = rows.saturating_sub(1);
+
+        // Scroll the palette so that the selected row is at the bottom if it is not in the first page.
+        let selected_row = self.selected / self.columns;
+        let first_row = (selected_row + 1).saturating_sub(palette_rows);
+
+        let recent = (0..self.recent.len().min(self.columns)).map(|i| (0, i, Cell::Recent(i)));
+        let palette = (first_row * self.columns..self.colors.len())
+            .take(palette_rows * self.columns)
+            .map(move |i| {
+                (
+                    i / self.columns - first_row + 1,
+                    i % self.columns,
+                    Cell::Palette(i),
+                )
+            });
+        recent
+            .chain(palette)
+            .filter(move |_| rows > 0)
+            .map(move |(row, column, cell)| {
+                let position = panel.position
+                    + Position::from_xy(
+                        (MARGIN + column as u32 * CELL_SIZE) as i32,
+                        (MARGIN + row as u32 * CELL_SIZE) as i32,
+                    );
+                (Region::new(position, Size::square(CELL_SIZE)), cell)
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Recent(usize),
+    Palette(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, palette::PaletteEntry};
+
+    #[test]
+    fn select_colors() {
+        let palette = Palette {
+            name: None,
+            columns: Some(2),
+            entries: (0..5)
+                .map(|i| PaletteEntry::new(Color::rgb(i, 0, 0)))
+                .collect(),
+        };
+        let mut panel = PalettePanel::new(&palette, &Config::default().palette);
+        let mut handle = |action| panel.handle_action(action);
+
+        assert_eq!(handle(PaletteAction::Select), Some(Color::rgb(0, 0, 0)));
+        handle(PaletteAction::Right);
+        handle(PaletteAction::Down);
+        assert_eq!(handle(PaletteAction::Select), Some(Color::rgb(3, 0, 0)));
+        handle(PaletteAction::Down);
+        assert_eq!(handle(PaletteAction::Select), Some(Color::rgb(3, 0, 0)));
+        handle(PaletteAction::Up);
+        handle(PaletteAction::Left);
+        handle(PaletteAction::Left);
+        assert_eq!(handle(PaletteAction::Select), Some(Color::rgb(0, 0, 0)));
+
+        // The initial brush color is not recorded.
+        for i in [9, 1, 2, 1] {
+            panel.track_brush_color(Color::rgb(i, 0, 0));
+        }
+        assert_eq!(
+            panel.recent_colors(),
+            [Color::rgb(1, 0, 0), Color::rgb(2, 0, 0)]
+        );
+        assert_eq!(
+            panel.handle_action(PaletteAction::Recent(2)),
+            Some(Color::rgb(2, 0, 0))
+        );
+        assert_eq!(panel.handle_action(PaletteAction::Recent(3)), None);
+
+        // The default key bindings.
+        let key = |s: &str| Key::try_from(s.to_owned()).unwrap().get();
+        assert_eq!(panel.get_action(key("]")), Some(PaletteAction::Right));
+        panel.handle_action(PaletteAction::Toggle);
+        assert_eq!(panel.get_action(key("]")), None);
+        assert_eq!(panel.get_action(key("Tab")), Some(PaletteAction::Toggle));
+    }
+
+    #[test]
+    fn layout() {
+        let palette = Palette {
+            name: None,
+            columns: Some(2),
+            entries: (0..10)
+                .map(|i| PaletteEntry::new(Color::rgb(i, 0, 0)))
+                .collect(),
+        };
+        let mut panel = PalettePanel::new(&palette, &Config::default().palette);
+        let area = Region::new(Position::from_xy(0, 7), Size::from_wh(40, 20));
+        let panel_region = panel.region(area).unwrap();
+        assert_eq!(panel_region.position, Position::from_xy(28, 7));
+        assert_eq!(panel_region.size, Size::from_wh(12, 20));
+        assert_eq!(panel.remaining_area(area).size, Size::from_wh(28, 20));
+
+        // One row for the recent colors and two rows for the palette are shown.
+        for _ in 0..5 {
+            panel.handle_action(PaletteAction::Down);
+        }
+        let clicked = panel.handle_click(area, Position::from_xy(29, 8 + 5));
+        assert_eq!(clicked, Some(Color::rgb(6, 0, 0)));
+    }
+}
@@ -0,0 +1,47 @@
+use orfail::OrFail;
+use pati::{Color, Point};
+use std::{io::Write, num::NonZeroU8};
+
+/// Maximum width and height of an image written by [`write_image()`] or [`crate::bmp::write_image()`].
+///
+/// The pixels are addressed by `Point`, so each side is limited to the range of `i16`.
+pub const MAX_IMAGE_SIZE: i32 = i16::MAX as i32 + 1;
+
+/// Gets the size of an image showing the given region (inclusive) at `scale`.
+///
+/// Fails if the size exceeds [`MAX_IMAGE_SIZE`].
+pub fn image_size((start, end): (Point, Point), scale: NonZeroU8) -> orfail::Result<(u16, u16)> {
+    let scale = i32::from(scale.get());
+    let width = (i32::from(end.x) - i32::from(start.x) + 1) * scale;
+    let height = (i32::from(end.y) - i32::from(start.y) + 1) * scale;
+    (width <= MAX_IMAGE_SIZE && height <= MAX_IMAGE_SIZE).or_fail_with(|()| {
+        format!("Too large image: {width}x{height} (max: {MAX_IMAGE_SIZE}x{MAX_IMAGE_SIZE})")
+    })?;
+    Ok((width as u16, height as u16))
+}
+
+pub fn write_image<W: Write>(
+    writer: W,
+    width: u16,
+    height: u16,
+    pixels: impl Iterator<Item = (Point, Color)>,
+) -> orfail::Result<()> {
+    let mut data = vec![0; width as usize * height as usize * 4];
+    for (point, c) in pixels {
+        if !(0..width as i32).contains(&(point.x as i32))
+            || !(0..height as i32).contains(&(point.y as i32))
+        {
+            continue;
+        }
+        let i = (point.y as usize * width as usize + point.x as usize) * 4;
+        data[i..i + 4].copy_from_slice(&[c.r, c.g, c.b, c.a]);
+    }
+
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().or_fail()?;
+    writer.write_image_data(&data).or_fail()?;
+    writer.finish().or_fail()?;
+    Ok(())
+}
@@ -1,17 +1,162 @@
-use pagurus::{image::Canvas, spatial::Size};
+use crate::font;
+use pagurus::{
+    image::Canvas,
+    spatial::{Position, Region, Size},
+};
+use pati::{Color, Point};
+use std::num::NonZeroU8;
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub struct Screen<'a> {
     canvas: Canvas<'a>,
-    screen_size: Size,
+    viewport: Viewport,
 }
 
 impl<'a> Screen<'a> {
     pub fn new(canvas: Canvas<'a>, screen_size: Size) -> Self {
         Self {
             canvas,
+            viewport: Viewport::new(screen_size),
+        }
+    }
+
+    /// Sets the point shown at the top-left corner of the screen and the size of a dot in pixels.
+    pub fn set_viewport(&mut self, top_left: Point, scale: NonZeroU8) {
+        self.viewport.top_left = top_left;
+        self.viewport.scale = scale;
+    }
+
+    /// Sets the viewport so that the camera point is shown at the center of the screen.
+    pub fn set_camera(&mut self, camera: Point, scale: NonZeroU8) {
+        self.viewport.set_camera(camera, scale);
+    }
+
+    /// Sets the region of the screen where the canvas is shown (the whole screen by default).
+    pub fn set_area(&mut self, area: Region) {
+        self.viewport.set_area(area);
+    }
+
+    pub fn screen_size(&self) -> Size {
+        self.viewport.screen_size
+    }
+
+    pub fn scale(&self) -> NonZeroU8 {
+        self.viewport.scale
+    }
+
+    /// Gets the rectangle (inclusive) of the points that are visible on the screen.
+    pub fn visible_range(&self) -> (Point, Point) {
+        self.viewport.visible_range()
+    }
+
+    pub fn fill_color(&mut self, color: Color) {
+        self.canvas.fill_color(to_pagurus_color(color));
+    }
+
+    pub fn dot(&mut self, point: Point, color: Color) {
+        let scale = self.viewport.scale.get() as i32;
+        let color = to_pagurus_color(color);
+        let p = self.point_to_position(point);
+        for y in 0..scale {
+            for x in 0..scale {
+                self.canvas.draw_pixel(p.move_x(x).move_y(y), color);
+            }
+        }
+    }
+
+    /// Fills the given region of the screen (in pixels, not in dots).
+    pub fn fill_region(&mut self, region: Region, color: Color) {
+        self.canvas
+            .mask_region(region)
+            .fill_color(to_pagurus_color(color));
+    }
+
+    /// Draws a line of text whose top-left corner is at the given position (in pixels).
+    pub fn draw_text(&mut self, position: Position, text: &str, color: Color) {
+        let color = to_pagurus_color(color);
+        for (i, c) in text.chars().enumerate() {
+            let left = position.x + (i as u32 * font::ADVANCE) as i32;
+            for (y, row) in font::glyph(c).into_iter().enumerate() {
+                for x in 0..font::GLYPH_SIZE.width {
+                    if row & (1 << (font::GLYPH_SIZE.width - 1 - x)) != 0 {
+                        let p = Position::from_xy(left + x as i32, position.y + y as i32);
+                        self.canvas.draw_pixel(p, color);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn point_to_position(&self, point: Point) -> Position {
+        self.viewport.point_to_position(point)
+    }
+
+    pub fn position_to_point(&self, position: Position) -> Point {
+        self.viewport.position_to_point(position)
+    }
+}
+
+/// Transform between canvas points and screen positions (in pixels).
+#[derive(Debug, Clone, Copy)]
+pub struct Viewport {
+    screen_size: Size,
+
+    /// Region of the screen where the canvas is shown.
+    area: Region,
+
+    top_left: Point,
+    scale: NonZeroU8,
+}
+
+impl Viewport {
+    pub fn new(screen_size: Size) -> Self {
+        Self {
             screen_size,
+            area: screen_size.to_region(),
+            top_left: Point::ORIGIN,
+            scale: NonZeroU8::MIN,
         }
     }
+
+    pub fn set_area(&mut self, area: Region) {
+        self.area = area;
+    }
+
+    /// Sets the viewport so that the camera point is shown at the center of the area.
+    pub fn set_camera(&mut self, camera: Point, scale: NonZeroU8) {
+        let dots = self.area.size / scale.get() as u32;
+        let center = Point::new((dots.width / 2) as i16, (dots.height / 2) as i16);
+        self.top_left = camera - center;
+        self.scale = scale;
+    }
+
+    /// Gets the rectangle (inclusive) of the points that are visible in the area.
+    pub fn visible_range(&self) -> (Point, Point) {
+        let end = self.position_to_point(self.area.end() - Position::from_xy(1, 1));
+        (self.top_left, end)
+    }
+
+    pub fn point_to_position(&self, point: Point) -> Position {
+        let scale = self.scale.get() as u32;
+        let delta = Position::from_xy(
+            point.x as i32 - self.top_left.x as i32,
+            point.y as i32 - self.top_left.y as i32,
+        );
+        delta * scale + self.area.position
+    }
+
+    pub fn position_to_point(&self, position: Position) -> Point {
+        let scale = self.scale.get() as i32;
+        let position = position - self.area.position;
+        let x = position.x.div_euclid(scale) + self.top_left.x as i32;
+        let y = position.y.div_euclid(scale) + self.top_left.y as i32;
+        Point::new(
+            x.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+            y.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+        )
+    }
+}
+
+fn to_pagurus_color(c: Color) -> pagurus::image::Color {
+    pagurus::image::Color::rgba(c.r, c.g, c.b, c.a)
 }
@@ -0,0 +1,158 @@
+use crate::{
+    config::{StatusItem, StatusLineConfig},
+    font,
+    history::color_to_hex,
+    screen::Screen,
+};
+use pagurus::spatial::{Position, Region, Size};
+use pati::{Color, Point, Version};
+use paticanvas::Canvas;
+
+const MARGIN: u32 = 1;
+
+/// Height of the status line (in pixels).
+pub const HEIGHT: u32 = font::GLYPH_SIZE.height + MARGIN * 2;
+
+/// Part of the status line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Text(String),
+    Swatch(Color),
+}
+
+/// Makes the segments of the items in `config` (items that are not applicable are omitted).
+pub fn segments(config: &StatusLineConfig, canvas: &Canvas, unsaved: usize) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    for item in &config.items {
+        let text = match item {
+            StatusItem::Mode => {
+                let mode = serde_json::to_value(canvas.mode()).expect("unreachable");
+                mode.as_str().expect("unreachable").to_owned()
+            }
+            StatusItem::Cursor => format_point(canvas.cursor()),
+            StatusItem::RelativeCursor => {
+                let Some((name, point)) = config.anchor.as_ref().and_then(|name| {
+                    let point = canvas.image().anchors().get(name)?;
+                    Some((name, *point))
+                }) else {
+                    continue;
+                };
+                format!("{name}:{}", format_point(canvas.cursor() - point))
+            }
+            StatusItem::Brush => {
+                segments.push(Segment::Swatch(canvas.brush_color()));
+                color_to_hex(canvas.brush_color())
+            }
+            StatusItem::Background => {
+                segments.push(Segment::Swatch(canvas.background_color()));
+                color_to_hex(canvas.background_color())
+            }
+            StatusItem::Scale => format!("x{}", canvas.scale()),
+            StatusItem::Version => {
+                let version = canvas.image().applied_commands(Version::default()).len();
+                format!("v{version}")
+            }
+            StatusItem::Sync => {
+                let stale = canvas.frames().values().filter(|f| f.is_stale()).count();
+                if stale > 0 {
+                    segments.push(Segment::Text(format!("stale:{stale}")));
+                }
+                if unsaved == 0 {
+                    "saved".to_owned()
+                } else {
+                    format!("unsaved:{unsaved}")
+                }
+            }
+            StatusItem::Marker => {
+                let Some(marker) = canvas.marker() else {
+                    continue;
+                };
+                let kind = serde_json::to_value(marker.kind()).expect("unreachable");
+                let kind = kind.as_str().expect("unreachable");
+                format!("{kind}:{}", marker.marked_points().count())
+            }
+        };
+        segments.push(Segment::Text(text));
+    }
+    segments
+}
+
+/// Renders the status line at the top of the screen (text that does not fit is cut off).
+pub fn render(config: &StatusLineConfig, canvas: &Canvas, unsaved: usize, screen: &mut Screen) {
+    let width = screen.screen_size().width;
+    let region = Region::new(Position::ORIGIN, Size::from_wh(width, HEIGHT));
+    screen.fill_region(region, Color::rgb(0, 0, 0));
+
+    let mut position = Position::from_xy(MARGIN as i32, MARGIN as i32);
+    for segment in segments(config, canvas, unsaved) {
+        match segment {
+            Segment::Text(text) => {
+                screen.draw_text(position, &text, Color::rgb(255, 255, 255));
+                position.x += (font::text_width(&text) + font::ADVANCE) as i32;
+            }
+            Segment::Swatch(color) => {
+                let size = Size::square(font::GLYPH_SIZE.height);
+                screen.fill_region(Region::new(position, size), color);
+                position.x += (size.width + MARGIN) as i32;
+            }
+        }
+    }
+}
+
+fn format_point(point: Point) -> String {
+    format!("{},{}", point.x, point.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paticanvas::{CanvasCommand, MarkKind};
+
+    #[test]
+    fn status_items() {
+        let mut canvas = Canvas::new();
+        let command = pati::ImageCommand::anchor("head", Some(Point::new(1, 1)));
+        canvas.command(&CanvasCommand::Image(command)).unwrap();
+        canvas
+            .command(&CanvasCommand::Dip(Color::rgb(255, 0, 0)))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Mark(MarkKind::Rectangle))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Move(Point::new(3, -2)))
+            .unwrap();
+
+        let config: StatusLineConfig = serde_json::from_str(
+            r#"{"items": ["mode", "cursor", "relative_cursor", "brush", "scale", "version", "sync", "marker"],
+                "anchor": "head"}"#,
+        )
+        .unwrap();
+        let text = |s: &str| Segment::Text(s.to_owned());
+        assert_eq!(
+            segments(&config, &canvas, 0),
+            [
+                text("marking"),
+                text("3,-2"),
+                text("head:2,-3"),
+                Segment::Swatch(Color::rgb(255, 0, 0)),
+                text("#ff0000"),
+                text("x1"),
+                text("v2"),
+                text("saved"),
+                text("rectangle:10"),
+            ]
+        );
+
+        // Unknown anchors and inactive markers are omitted.
+        let config: StatusLineConfig = serde_json::from_str(
+            r#"{"items": ["relative_cursor", "marker", "sync", "mode"], "anchor": "x"}"#,
+        )
+        .unwrap();
+        canvas.command(&CanvasCommand::Cancel).unwrap();
+        assert_eq!(
+            segments(&config, &canvas, 2),
+            [text("unsaved:2"), text("neutral")]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use orfail::OrFail;
+use pati::{Color, Image, Point};
+use std::{collections::BTreeMap, io::Write};
+
+/// Writes the given image as an SVG document.
+///
+/// Horizontally adjacent pixels of the same color are merged into a single run,
+/// and all runs of a color are emitted as one `<path>` element to keep the output small.
+pub fn write_image<W: Write>(
+    mut writer: W,
+    image: &Image,
+    background_color: Option<Color>,
+) -> orfail::Result<()> {
+    let (start, end) = image
+        .bounding_box()
+        .unwrap_or((Point::ORIGIN, Point::ORIGIN));
+    let width = (end.x as i32 - start.x as i32) + 1;
+    let height = (end.y as i32 - start.y as i32) + 1;
+
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {width} {height}" width="{width}" height="{height}" shape-rendering="crispEdges">"#,
+        start.x, start.y
+    )
+    .or_fail()?;
+
+    if let Some(color) = background_color {
+        writeln!(
+            writer,
+            r#"<rect x="{}" y="{}" width="{width}" height="{height}"{}/>"#,
+            start.x,
+            start.y,
+            fill_attrs(color)
+        )
+        .or_fail()?;
+    }
+
+    for (color, runs) in collect_runs(image) {
+        write!(writer, r#"<path{} d=""#, fill_attrs(color)).or_fail()?;
+        for (point, len) in runs {
+            write!(writer, "M{} {}h{len}v1h-{len}z", point.x, point.y).or_fail()?;
+        }
+        writeln!(writer, r#""/>"#).or_fail()?;
+    }
+
+    writeln!(writer, "</svg>").or_fail()?;
+    Ok(())
+}
+
+fn collect_runs(image: &Image) -> BTreeMap<Color, Vec<(Point, u32)>> {
+    let mut runs: BTreeMap<Color, Vec<(Point, u32)>> = BTreeMap::new();
+    let mut current: Option<(Point, Color, u32)> = None;
+    for (&point, &color) in image.pixels() {
+        if let Some((start, c, len)) = &mut current {
+            if *c == color && start.y == point.y && start.x as i32 + *len as i32 == point.x as i32 {
+                *len += 1;
+                continue;
+            }
+            runs.entry(*c).or_default().push((*start, *len));
+        }
+        current = Some((point, color, 1));
+    }
+    if let Some((start, color, len)) = current {
+        runs.entry(color).or_default().push((start, len));
+    }
+    runs
+}
+
+fn fill_attrs(color: Color) -> String {
+    let mut attrs = format!(
+        r##" fill="#{:02x}{:02x}{:02x}""##,
+        color.r, color.g, color.b
+    );
+    if color.a != 255 {
+        attrs.push_str(&format!(r#" fill-opacity="{:.3}""#, color.a as f32 / 255.0));
+    }
+    attrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pati::ImageCommand;
+
+    #[test]
+    fn merge_runs() {
+        let (red, blue) = (Color::rgb(255, 0, 0), Color::rgba(0, 0, 255, 128));
+        let mut image = Image::new();
+        let pixels = [
+            (0, 0, red),
+            (1, 0, red),
+            (2, 0, red),
+            (3, 0, blue),
+            (4, 0, red),
+        ]
+        .into_iter()
+        .chain([(-1, 1, red), (0, 1, red), (2, 1, red)])
+        .map(|(x, y, c)| (Point::new(x, y), c));
+        image.apply(&ImageCommand::draw_pixels(pixels));
+
+        let mut svg = Vec::new();
+        write_image(&mut svg, &image, None).unwrap();
+        assert_eq!(
+            String::from_utf8(svg).unwrap(),
+            [
+                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="-1 0 6 2" width="6" height="2" shape-rendering="crispEdges">"#,
+                r##"<path fill="#0000ff" fill-opacity="0.502" d="M3 0h1v1h-1z"/>"##,
+                r##"<path fill="#ff0000" d="M0 0h3v1h-3zM4 0h1v1h-1zM-1 1h2v1h-2zM2 1h1v1h-1z"/>"##,
+                "</svg>\n",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use crate::palette::Palette;
+use orfail::OrFail;
+use pati::{Color, ImageCommand, Point};
+use paticanvas::{Grid, METADATA_BACKGROUND_COLOR, METADATA_GRID};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    num::NonZeroU16,
+    path::{Path, PathBuf},
+};
+
+pub const ANCHOR_CANVAS_START: &str = "canvas.start";
+pub const ANCHOR_CANVAS_END: &str = "canvas.end";
+
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("default", include_str!("../templates/default.json")),
+    ("sprite16", include_str!("../templates/sprite16.json")),
+    ("sprite32", include_str!("../templates/sprite32.json")),
+];
+
+/// Initial contents of a new file.
+///
+/// Templates are looked up in `$XDG_CONFIG_HOME/patica/templates/<NAME>.json`
+/// (or `~/.config/patica/templates/<NAME>.json`) first, and then in the built-in ones.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Template {
+    /// Canvas size, which is recorded as the `canvas.start` and `canvas.end` anchors.
+    #[serde(default)]
+    pub size: Option<(NonZeroU16, NonZeroU16)>,
+
+    #[serde(default)]
+    pub background_color: Option<Color>,
+
+    #[serde(default)]
+    pub anchors: BTreeMap<String, Point>,
+
+    /// Named regions, which are recorded as the `<NAME>.start` and `<NAME>.end` anchors.
+    #[serde(default)]
+    pub regions: BTreeMap<String, (Point, Point)>,
+
+    #[serde(default)]
+    pub grid: Option<Grid>,
+
+    /// Palette file (`.gpl`, `.pal`, `.hex` or `.pati`) drawn to the right of the canvas.
+    ///
+    /// A relative path is resolved from the directory of the template file.
+    #[serde(default)]
+    pub palette: Option<PathBuf>,
+
+    /// Directory of the template file (`None` for the built-in templates).
+    #[serde(skip)]
+    pub base_dir: Option<PathBuf>,
+}
+
+impl Template {
+    pub fn load(name: &str) -> orfail::Result<Self> {
+        if let Some(dir) = Self::user_templates_dir() {
+            let path = dir.join(format!("{name}.json"));
+            if path.exists() {
+                return Self::load_file(&path).or_fail();
+            }
+        }
+        let (_, json) = BUILTIN_TEMPLATES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .or_fail_with(|()| format!("No such template: {name}"))?;
+        serde_json::from_str(json).or_fail()
+    }
+
+    pub fn load_file(path: &Path) -> orfail::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .or_fail_with(|e| format!("Failed to read template file {}: {e}", path.display()))?;
+        let mut template: Self = serde_json::from_str(&json).or_fail_with(|e| {
+            format!(
+                "Failed to parse template file: path={}, reason={e}",
+                path.display()
+            )
+        })?;
+        template.base_dir = path.parent().map(|p| p.to_path_buf());
+        Ok(template)
+    }
+
+    pub fn user_templates_dir() -> Option<PathBuf> {
+        crate::config::config_dir().map(|dir| dir.join("templates"))
+    }
+
+    pub fn to_image_commands(&self) -> orfail::Result<Vec<ImageCommand>> {
+        let mut commands = Vec::new();
+        if let Some(color) = self.background_color {
+            commands.push(ImageCommand::put(
+                METADATA_BACKGROUND_COLOR,
+                serde_json::to_value(color).or_fail()?,
+            ));
+        }
+        if let Some(grid) = self.grid {
+            commands.push(ImageCommand::put(
+                METADATA_GRID,
+                serde_json::to_value(grid).or_fail()?,
+            ));
+        }
+
+        let canvas_end = self.size.map(|(w, h)| {
+            let coordinate = |n: NonZeroU16| {
+                i16::try_from(n.get() - 1)
+                    .or_fail_with(|_| format!("Too large canvas size: {w}x{h} (max: 32768x32768)"))
+            };
+            Ok::<_, orfail::Failure>(Point::new(coordinate(w)?, coordinate(h)?))
+        });
+        let canvas_end = canvas_end.transpose().or_fail()?;
+        if let Some(path) = &self.palette {
+            let path = match &self.base_dir {
+                Some(dir) => dir.join(path),
+                None => path.clone(),
+            };
+            let palette = Palette::load(&path).or_fail()?;
+            let x = canvas_end
+                .map_or(0, |p| p.x)
+                .checked_add(2)
+                .or_fail_with(|()| {
+                    "No room for the palette to the right of the canvas".to_owned()
+                })?;
+            let top_left = Point::new(x, 0);
+            commands.extend(palette.to_image_commands(top_left).or_fail()?);
+        }
+
+        // Anchors are set after the palette to override its `origin` anchor.
+        if let Some(end) = canvas_end {
+            commands.push(ImageCommand::anchor(
+                ANCHOR_CANVAS_START,
+                Some(Point::ORIGIN),
+            ));
+            commands.push(ImageCommand::anchor(ANCHOR_CANVAS_END, Some(end)));
+        }
+        for (name, (start, end)) in &self.regions {
+            commands.push(ImageCommand::anchor(format!("{name}.start"), Some(*start)));
+            commands.push(ImageCommand::anchor(format!("{name}.end"), Some(*end)));
+        }
+        for (name, point) in &self.anchors {
+            commands.push(ImageCommand::anchor(name, Some(*point)));
+        }
+        Ok(commands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use paticanvas::{Canvas, CanvasCommand};
+
+    fn builtin(name: &str) -> Template {
+        let (_, json) = BUILTIN_TEMPLATES.iter().find(|(n, _)| *n == name).unwrap();
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn builtin_templates() {
+        for (name, _) in BUILTIN_TEMPLATES {
+            assert!(!builtin(name).to_image_commands().unwrap().is_empty());
+        }
+
+        let mut canvas = Canvas::new();
+        for command in builtin("sprite16").to_image_commands().unwrap() {
+            canvas.command(&CanvasCommand::Image(command)).unwrap();
+        }
+        let anchors = canvas.image().anchors();
+        assert_eq!(anchors.get(ANCHOR_CANVAS_START), Some(&Point::ORIGIN));
+        assert_eq!(anchors.get(ANCHOR_CANVAS_END), Some(&Point::new(15, 15)));
+        assert_eq!(anchors.get("origin"), Some(&Point::new(8, 8)));
+        assert_eq!(canvas.background_color(), Color::rgb(230, 230, 230));
+
+        let grid = canvas.grid().unwrap();
+        assert_eq!((grid.width.get(), grid.height.get()), (8, 8));
+        assert_eq!(grid.color, Color::rgba(0, 0, 0, 32));
+    }
+
+    #[test]
+    fn too_large_size() {
+        let template: Template = serde_json::from_str(r#"{"size": [32768, 40000]}"#).unwrap();
+        assert!(template.to_image_commands().is_err());
+
+        let template: Template = serde_json::from_str(r#"{"size": [32768, 32768]}"#).unwrap();
+        let commands = template.to_image_commands().unwrap();
+        assert_eq!(
+            serde_json::to_string(commands.last().unwrap()).unwrap(),
+            r#"{"anchor":{"name":"canvas.end","point":[32767,32767]}}"#
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use orfail::OrFail;
+use pati::{Color, Image, ImageCommand, Point, Version, VersionedImage};
+use std::{
+    io::{BufWriter, Write},
+    num::{NonZeroU8, NonZeroUsize},
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Sampling {
+    /// Takes a frame every N versions.
+    Versions(NonZeroUsize),
+
+    /// Takes a frame every time N or more pixels have been changed since the last frame.
+    Pixels(NonZeroUsize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Timelapse {
+    frames: Vec<Image>,
+    region: (Point, Point),
+    scale: NonZeroU8,
+    background_color: Option<Color>,
+}
+
+impl Timelapse {
+    /// Replays the history of the given image and takes the sampled frames.
+    ///
+    /// All frames are cropped to the bounding box of the final image.
+    /// The final image is always included as the last frame.
+    pub fn new(image: &VersionedImage, sampling: Sampling) -> Self {
+        let mut frames = Vec::new();
+        let mut current = Image::new();
+        let mut versions = 0;
+        let mut changed_pixels = 0;
+        for command in image.applied_commands(Version::default()) {
+            changed_pixels += count_changed_pixels(&current, command);
+            current.apply(command);
+            versions += 1;
+
+            let take = match sampling {
+                Sampling::Versions(n) => versions >= n.get(),
+                Sampling::Pixels(n) => changed_pixels >= n.get(),
+            };
+            if take {
+                frames.push(current.clone());
+                versions = 0;
+                changed_pixels = 0;
+            }
+        }
+        if versions > 0 || frames.is_empty() {
+            frames.push(current);
+        }
+
+        Self {
+            frames,
+            region: image
+                .bounding_box()
+                .unwrap_or((Point::ORIGIN, Point::ORIGIN)),
+            scale: NonZeroU8::MIN,
+            background_color: None,
+        }
+    }
+
+    pub fn set_scale(&mut self, scale: NonZeroU8) {
+        self.scale = scale;
+    }
+
+    pub fn set_background_color(&mut self, color: Option<Color>) {
+        self.background_color = color;
+    }
+
+    pub fn frames(&self) -> &[Image] {
+        &self.frames
+    }
+
+    /// Writes the frames as an animated GIF.
+    ///
+    /// `delay` is the duration of each frame in hundredths of a second,
+    /// and the final frame is displayed for additional `hold` frames.
+    pub fn write_gif<W: Write>(&self, writer: W, delay: u16, hold: u16) -> orfail::Result<()> {
+        let (width, height) = self.size().or_fail()?;
+        let mut encoder = gif::Encoder::new(writer, width, height, &[]).or_fail()?;
+        encoder.set_repeat(gif::Repeat::Infinite).or_fail()?;
+        for (i, image) in self.frames.iter().enumerate() {
+            let mut data = self.rasterize(image, width, height);
+            let mut frame = gif::Frame::from_rgba_speed(width, height, &mut data, 10);
+            frame.delay = if i + 1 == self.frames.len() {
+                delay.saturating_mul(hold.saturating_add(1))
+            } else {
+                delay
+            };
+            encoder.write_frame(&frame).or_fail()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the frames as a numbered PNG sequence (`00000.png`, `00001.png`, ...) into `dir`.
+    ///
+    /// The final frame is written additional `hold` times.
+    pub fn write_png_sequence<P: AsRef<Path>>(&self, dir: P, hold: u16) -> orfail::Result<()> {
+        let dir = dir.as_ref();
+        let (width, height) = self.size().or_fail()?;
+        std::fs::create_dir_all(dir).or_fail()?;
+
+        let last = self.frames.last().or_fail()?;
+        let frames = self
+            .frames
+            .iter()
+            .chain(std::iter::repeat_n(last, hold as usize));
+        for (i, image) in frames.enumerate() {
+            let path = dir.join(format!("{i:05}.png"));
+            let file = std::fs::File::create(&path)
+                .or_fail_with(|e| format!("Failed to create file {}: {e}", path.display()))?;
+            crate::png::write_image(BufWriter::new(file), width, height, self.pixels(image))
+                .or_fail()?;
+        }
+        Ok(())
+    }
+
+    /// Gets the size of the frames.
+    fn size(&self) -> orfail::Result<(u16, u16)> {
+        crate::png::image_size(self.region, self.scale).or_fail()
+    }
+
+    /// Gets the cropped and scaled pixels of the given frame.
+    fn pixels<'a>(&'a self, image: &'a Image) -> impl 'a + Iterator<Item = (Point, Color)> {
+        let (start, end) = self.region;
+        let scale = self.scale.get() as i16;
+        let background = self.background_color.into_iter().flat_map(move |color| {
+            (start.y..=end.y)
+                .flat_map(move |y| {
+                    (start.x..=end.x).map(move |x| (Point::new(x, y) - start, color))
+                })
+                .filter(move |(point, _)| image.get_pixel(*point + start).is_none())
+        });
+        image
+            .range_pixels(start..=end)
+            .map(move |(point, color)| (point - start, color))
+            .chain(background)
+            .flat_map(move |(point, color)| {
+                (0..scale).flat_map(move |y| {
+                    (0..scale).map(move |x| (point * scale + Point::new(x, y), color))
+                })
+            })
+    }
+
+    fn rasterize(&self, image: &Image, width: u16, height: u16) -> Vec<u8> {
+        let mut data = vec![0; width as usize * height as usize * 4];
+        for (point, c) in self.pixels(image) {
+            let i = (point.y as usize * width as usize + point.x as usize) * 4;
+            data[i..i + 4].copy_from_slice(&[c.r, c.g, c.b, c.a]);
+        }
+        data
+    }
+}
+
+fn count_changed_pixels(image: &Image, command: &ImageCommand) -> usize {
+    let ImageCommand::Patch(patch) = command else {
+        return 0;
+    };
+    patch
+        .entries()
+        .iter()
+        .flat_map(|entry| entry.points.iter().map(move |&p| (p, entry.color)))
+        .filter(|&(point, color)| image.get_pixel(point) != color)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pati::PatchEntry;
+
+    fn history(points: &[Point]) -> VersionedImage {
+        let mut image = VersionedImage::new();
+        for &point in points {
+            let color = Color::rgb(255, 0, 0);
+            image.apply(&ImageCommand::patch(vec![PatchEntry::draw(
+                color,
+                vec![point],
+            )]));
+        }
+        image
+    }
+
+    #[test]
+    fn frames_and_size() {
+        let image = history(&[Point::new(0, 0), Point::new(1, 0), Point::new(2, 1)]);
+        let versions = |n| Sampling::Versions(NonZeroUsize::new(n).unwrap());
+        assert_eq!(Timelapse::new(&image, versions(1)).frames().len(), 3);
+        assert_eq!(Timelapse::new(&image, versions(2)).frames().len(), 2);
+        assert_eq!(Timelapse::new(&image, versions(5)).frames().len(), 1);
+        let pixels = Sampling::Pixels(NonZeroUsize::new(2).unwrap());
+        assert_eq!(Timelapse::new(&image, pixels).frames().len(), 2);
+        assert_eq!(
+            Timelapse::new(&VersionedImage::new(), versions(1))
+                .frames()
+                .len(),
+            1
+        );
+
+        let mut timelapse = Timelapse::new(&image, versions(1));
+        assert_eq!(timelapse.size().unwrap(), (3, 2));
+        timelapse.set_scale(NonZeroU8::new(4).unwrap());
+        assert_eq!(timelapse.size().unwrap(), (12, 8));
+        let data = timelapse.rasterize(&timelapse.frames()[0], 12, 8);
+        assert_eq!(&data[..4], [255, 0, 0, 255]);
+        assert_eq!(&data[4 * 4..4 * 4 + 4], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn too_large_size() {
+        let image = history(&[Point::new(i16::MIN, 0), Point::new(i16::MAX, 0)]);
+        let timelapse = Timelapse::new(&image, Sampling::Versions(NonZeroUsize::MIN));
+        assert!(timelapse.size().is_err());
+
+        let image = history(&[Point::new(0, 0), Point::new(300, 0)]);
+        let mut timelapse = Timelapse::new(&image, Sampling::Versions(NonZeroUsize::MIN));
+        timelapse.set_scale(NonZeroU8::new(108).unwrap());
+        assert_eq!(timelapse.size().unwrap(), (32508, 108));
+        timelapse.set_scale(NonZeroU8::new(109).unwrap());
+        assert!(timelapse.size().is_err());
+        assert!(timelapse.write_gif(std::io::sink(), 10, 0).is_err());
+    }
+}
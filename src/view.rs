@@ -1,191 +1,529 @@
-use crate::{model::Model, screen::Screen};
-use pagurus::{event::Event, System};
+use crate::{
+    config::StatusLineConfig,
+    font,
+    keymap::{KeyInput, KeyInputResult, Keymap},
+    minibuffer::{Minibuffer, MinibufferAction},
+    model::Model,
+    palette_panel::PalettePanel,
+    screen::{Screen, Viewport},
+    status_line,
+    template::{ANCHOR_CANVAS_END, ANCHOR_CANVAS_START},
+};
+use orfail::OrFail;
+use pagurus::{
+    event::{Event, Key, KeyEvent, MouseEvent},
+    spatial::{Contains, Position, Region, Size},
+    System,
+};
+use pati::{Color, Point};
+use paticanvas::{Canvas, CanvasCommand, CanvasMode, Grid, MarkKind};
+use std::time::Duration;
 
 #[derive(Debug, Default)]
-pub struct View {}
+pub struct View {
+    keymap: Keymap,
+    key_input: KeyInput,
+    cursor: CursorBlink,
+    screen_size: Size,
+    drag: Option<Drag>,
+    minibuffer: Minibuffer,
+    status_line: Option<StatusLineConfig>,
+    palette_panel: Option<PalettePanel>,
+}
 
 impl View {
-    pub fn render(&self, _model: &Model, _screen: &mut Screen) {}
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    pub fn set_status_line(&mut self, status_line: Option<StatusLineConfig>) {
+        self.status_line = status_line;
+    }
+
+    pub fn set_palette_panel(&mut self, palette_panel: Option<PalettePanel>) {
+        self.palette_panel = palette_panel;
+    }
+
+    /// Gets the region of the screen below the status line.
+    fn content_area(&self) -> Region {
+        let mut area = self.screen_size.to_region();
+        if self.status_line.is_some() {
+            let height = status_line::HEIGHT.min(area.size.height);
+            area.position.y += height as i32;
+            area.size.height -= height;
+        }
+        area
+    }
+
+    /// Gets the region of the screen where the canvas is shown.
+    fn canvas_area(&self) -> Region {
+        let area = self.content_area();
+        self.palette_panel
+            .as_ref()
+            .map_or(area, |panel| panel.remaining_area(area))
+    }
+
+    pub fn render(&self, model: &Model, screen: &mut Screen) {
+        if screen.screen_size().to_region().is_empty() {
+            // Not initialized yet.
+            return;
+        }
+
+        let canvas = model.canvas();
+        screen.set_area(self.canvas_area());
+        screen.set_camera(canvas.camera(), canvas.scale());
+        render_canvas(canvas, screen);
+        if self.cursor.visible {
+            render_cursor(canvas, screen);
+        }
+        if let Some(panel) = &self.palette_panel {
+            panel.render(self.content_area(), screen);
+        }
+        if let Some(config) = &self.status_line {
+            status_line::render(config, canvas, model.unsaved_commands(), screen);
+        }
+        if self.minibuffer.is_active() {
+            render_minibuffer(&self.minibuffer, screen);
+        } else if let Some(text) = self.key_input.pending_text() {
+            render_pending_keys(&text, screen);
+        }
+    }
+
+    pub fn handle_resize(&mut self, size: Size) {
+        self.screen_size = size;
+    }
+
+    pub fn handle_tick(&mut self, model: &Model, now: Duration) {
+        self.cursor.tick(now);
+        self.key_input.handle_tick(now);
+        self.track_brush_color(model);
+    }
+
+    fn track_brush_color(&mut self, model: &Model) {
+        if let Some(panel) = &mut self.palette_panel {
+            panel.track_brush_color(model.canvas().brush_color());
+        }
+    }
 
     pub fn handle_event<S: System>(
         &mut self,
-        _system: &S,
-        _model: &mut Model,
-        _event: Event,
+        system: &S,
+        model: &mut Model,
+        event: Event,
     ) -> orfail::Result<()> {
-        //         self.cursor.handle_event(system, model, event).or_fail()?;
+        let now = system.clock_game_time();
+        match event {
+            Event::Key(key) => {
+                self.cursor.reset(now);
+                self.handle_key_event(model, key, now).or_fail()?;
+            }
+            Event::Mouse(event) => {
+                self.cursor.reset(now);
+                self.handle_mouse_event(model, event).or_fail()?;
+            }
+            _ => return Ok(()),
+        }
+        self.track_brush_color(model);
+        Ok(())
+    }
+
+    fn handle_key_event(
+        &mut self,
+        model: &mut Model,
+        key: KeyEvent,
+        now: Duration,
+    ) -> orfail::Result<()> {
+        if self.minibuffer.is_active() {
+            if let MinibufferAction::Execute(command) =
+                self.minibuffer.handle_key(key, model.canvas())
+            {
+                self.minibuffer.set_result(model.command(&command));
+            }
+            return Ok(());
+        }
+        // `:` always opens the minibuffer (it cannot be rebound in the config).
+        if key == KeyEvent::from(Key::Char(':')) {
+            self.minibuffer.open();
+            return Ok(());
+        }
+        if let Some(panel) = &mut self.palette_panel {
+            if let Some(action) = panel
+                .get_action(key)
+                .filter(|_| self.key_input.pending_text().is_none())
+            {
+                if let Some(color) = panel.handle_action(action) {
+                    // A failing command is ignored as with the key bindings below.
+                    model.command(&CanvasCommand::Dip(color)).ok();
+                }
+                return Ok(());
+            }
+        }
 
-        //         let Event::Key(key) = event else {
-        //             return Ok(());
-        //         };
-        //         for command in self.key_config.get_commands(key) {
-        //             model.apply(command);
-        //         }
+        let mode = model.canvas().mode();
+        let KeyInputResult::Matched { commands, count } =
+            self.key_input.handle_key(&self.keymap, mode, key, now)
+        else {
+            return Ok(());
+        };
+        for _ in 0..count {
+            for command in commands {
+                // A command that is not applicable in the current mode (e.g., `rotate` while not
+                // editing) just stops the rest of the sequence instead of terminating the editor.
+                if model.command(command).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles left button events (the terminal backend does not report the other buttons and
+    /// the wheel).
+    ///
+    /// A click moves the cursor (or selects a color in the palette panel),
+    /// and a drag marks a stroke and draws it on release.
+    /// If the canvas is already in the marking mode (e.g., a rectangle is being marked by keys),
+    /// a drag moves the end of the mark instead.
+    fn handle_mouse_event(&mut self, model: &mut Model, event: MouseEvent) -> orfail::Result<()> {
+        let area = self.content_area();
+        if let (MouseEvent::Down { position, .. }, Some(panel)) = (event, &mut self.palette_panel) {
+            if panel.region(area).is_some_and(|r| r.contains(&position)) {
+                if let Some(color) = panel.handle_click(area, position) {
+                    // A failing command is ignored as with the clicks on the canvas below.
+                    model.command(&CanvasCommand::Dip(color)).ok();
+                }
+                return Ok(());
+            }
+        }
 
+        let canvas = model.canvas();
+        let mut viewport = Viewport::new(self.screen_size);
+        viewport.set_area(self.canvas_area());
+        viewport.set_camera(canvas.camera(), canvas.scale());
+        let delta = viewport.position_to_point(event.position()) - canvas.cursor();
+        let mode = canvas.mode();
+
+        // As with key bindings, a command that fails (e.g., drawing while its embedded frame is
+        // broken) just stops handling the event instead of terminating the editor.
+        match event {
+            MouseEvent::Down { .. } => {
+                if model.command(&CanvasCommand::Move(delta)).is_err() {
+                    return Ok(());
+                }
+                self.drag = Some(Drag { stroke: false });
+            }
+            MouseEvent::Move { .. } => {
+                let Some(drag) = &mut self.drag else {
+                    return Ok(());
+                };
+                if !drag.stroke && mode == CanvasMode::Neutral {
+                    drag.stroke = model
+                        .command(&CanvasCommand::Mark(MarkKind::Stroke))
+                        .is_ok();
+                }
+                if model.command(&CanvasCommand::Move(delta)).is_err() {
+                    return Ok(());
+                }
+            }
+            MouseEvent::Up { .. } => {
+                let Some(drag) = self.drag.take() else {
+                    return Ok(());
+                };
+                if model.command(&CanvasCommand::Move(delta)).is_err() {
+                    return Ok(());
+                }
+                if drag.stroke && model.canvas().mode() == CanvasMode::Marking {
+                    // If the stroke cannot be drawn, it is left marked so that it can be retried.
+                    for command in [CanvasCommand::Draw, CanvasCommand::Cancel] {
+                        if model.command(&command).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
         Ok(())
     }
 }
 
-// use crate::{config::KeyConfig, model::Model};
-// use orfail::OrFail;
-// use pagurus::{
-//     event::Event,
-//     image::Canvas,
-//     spatial::{Position, Size},
-//     System,
-// };
-// use pati::{Color, Point};
-// use std::{collections::BTreeSet, time::Duration};
-
-// #[derive(Debug, Default)]
-// pub struct View {
-//     key_config: KeyConfig,
-//     cursor: Cursor,
-// }
-
-// impl View {
-//     pub fn set_key_config(&mut self, config: KeyConfig) {
-//         self.key_config = config;
-//     }
-
-//     pub fn render(&self, model: &Model, canvas: &mut WindowCanvas) {
-//         self.render_background(model, canvas);
-//         self.render_frames(model, canvas);
-
-//         let marked_points = self.collect_marked_points(model);
-//         self.render_pixels(model, canvas, &marked_points);
-//         self.cursor.render(model, canvas, &marked_points);
-//     }
-
-//     fn render_frames(&self, model: &Model, canvas: &mut WindowCanvas) {
-//         for frame in model
-//             .frames()
-//             .values()
-//             .filter(|f| f.frame.is_visible(model.ticks()))
-//         {
-//             for (&point, &color) in frame.pixels.iter() {
-//                 canvas.dot(model, point, color);
-//             }
-//         }
-//     }
-
-//     fn collect_marked_points(&self, model: &Model) -> BTreeSet<Point> {
-//         let mut points = BTreeSet::new();
-//         if let Some(marker) = model.marker() {
-//             points.extend(marker.marked_points());
-//         } else {
-//             points.insert(model.cursor());
-//         }
-//         points
-//     }
-
-//     fn render_pixels(
-//         &self,
-//         model: &Model,
-//         canvas: &mut WindowCanvas,
-//         marked_points: &BTreeSet<Point>,
-//     ) {
-//         let top_left = canvas.position_to_point(model, Position::ORIGIN);
-//         let bottom_right = canvas.position_to_point(model, canvas.window_size.to_region().end());
-//         for (point, color) in model.canvas().range_pixels(top_left..bottom_right) {
-//             if marked_points.contains(&point) {
-//                 continue;
-//             }
-//             canvas.dot(model, point, color);
-//         }
-//     }
-
-//     fn render_background(&self, model: &Model, canvas: &mut WindowCanvas) {
-//         let c = model.background_color();
-//         canvas
-//             .canvas
-//             .fill_color(pagurus::image::Color::rgba(c.r, c.g, c.b, c.a));
-//     }
-
-// }
-
-// // TODO: rename
-// #[derive(Debug, Default, Clone, Copy)]
-// struct Cursor {
-//     show: bool,
-//     switch_time: Duration,
-// }
-
-// impl Cursor {
-//     fn render(self, model: &Model, canvas: &mut WindowCanvas, marked_points: &BTreeSet<Point>) {
-//         let color = model.brush_color();
-//         if self.show {
-//             if let Some(editor) = model.editor() {
-//                 for (point, color) in editor.pixels() {
-//                     canvas.dot(model, point + model.cursor(), color);
-//                 }
-//             } else {
-//                 for &point in marked_points {
-//                     canvas.dot(model, point, color);
-//                 }
-//             }
-//         }
-//     }
-
-//     fn handle_event<S: System>(
-//         &mut self,
-//         system: &S,
-//         _model: &mut Model,
-//         event: Event,
-//     ) -> orfail::Result<()> {
-//         if matches!(event, Event::Key(_)) {
-//             self.show = true;
-//             self.switch_time = system.clock_game_time() + Duration::from_secs(1);
-//         }
-
-//         if system.clock_game_time() >= self.switch_time {
-//             self.show = !self.show;
-//             self.switch_time = system.clock_game_time() + Duration::from_millis(500);
-//         }
-
-//         Ok(())
-//     }
-// }
-
-// #[derive(Debug)]
-// pub struct WindowCanvas<'a> {
-//     canvas: Canvas<'a>,
-//     window_size: Size,
-// }
-
-// impl<'a> WindowCanvas<'a> {
-//     pub fn new(canvas: Canvas<'a>, window_size: Size) -> Self {
-//         Self {
-//             canvas,
-//             window_size,
-//         }
-//     }
-
-//     fn dot(&mut self, model: &Model, point: Point, color: Color) {
-//         let scale = model.scale().get() as u32;
-//         let color = pagurus::image::Color::rgba(color.r, color.g, color.b, color.a);
-//         let p = self.point_to_position(model, point);
-//         for y in 0..scale {
-//             for x in 0..scale {
-//                 self.canvas
-//                     .draw_pixel(p.move_x(x as i32).move_y(y as i32), color);
-//             }
-//         }
-//     }
-
-//     fn point_to_position(&self, model: &Model, point: Point) -> Position {
-//         let scale = model.scale().get() as u32;
-//         let center = (self.window_size / scale).to_region().center();
-//         let point_position = Position::from_xy(point.x as i32, point.y as i32);
-//         let camera_position = Position::from_xy(model.camera().x as i32, model.camera().y as i32);
-//         (point_position - camera_position + center) * scale
-//     }
-
-//     fn position_to_point(&self, model: &Model, position: Position) -> Point {
-//         let scale = model.scale().get() as u32;
-//         let center = (self.window_size / scale).to_region().center();
-//         let camera_position = Position::from_xy(model.camera().x as i32, model.camera().y as i32);
-//         let p = (position / scale) + camera_position - center;
-//         Point::new(p.x as i16, p.y as i16)
-//     }
-// }
+/// Renders the background, the visible frames and the pixels of the given canvas.
+///
+/// The grid and the canvas bounds (`canvas.start` and `canvas.end` anchors) are drawn over them
+/// if they are set by the template.
+/// In the editing mode, the pixels being placed are drawn over them at the cursor.
+pub fn render_canvas(canvas: &Canvas, screen: &mut Screen) {
+    screen.fill_color(canvas.background_color());
+    let (start, end) = screen.visible_range();
+    for (point, color) in canvas.visible_pixels(start, end) {
+        screen.dot(point, color);
+    }
+
+    let anchors = canvas.image().anchors();
+    let bounds = anchors
+        .get(ANCHOR_CANVAS_START)
+        .zip(anchors.get(ANCHOR_CANVAS_END))
+        .map(|(&start, &end)| (start, end))
+        .filter(|(start, end)| start.x <= end.x && start.y <= end.y);
+    if let Some(grid) = canvas.grid() {
+        render_grid(grid, bounds, screen);
+    }
+    if let Some((start, end)) = bounds {
+        // Outline just outside the bounds so that it does not hide the pixels on the edges.
+        let start = screen.point_to_position(start) - Position::from_xy(1, 1);
+        let end =
+            screen.point_to_position(end) + Position::from_xy(1, 1) * screen.scale().get() as u32;
+        let color = Color::rgba(0, 0, 0, 128);
+        let width = (end.x - start.x + 1) as u32;
+        let height = (end.y - start.y + 1) as u32;
+        screen.fill_region(Region::new(start, Size::from_wh(width, 1)), color);
+        screen.fill_region(
+            Region::new(Position::from_xy(start.x, end.y), Size::from_wh(width, 1)),
+            color,
+        );
+        screen.fill_region(Region::new(start, Size::from_wh(1, height)), color);
+        screen.fill_region(
+            Region::new(Position::from_xy(end.x, start.y), Size::from_wh(1, height)),
+            color,
+        );
+    }
+    if let Some(editor) = canvas.editor() {
+        for (point, color) in editor.pixels() {
+            screen.dot(point + canvas.cursor(), color);
+        }
+    }
+}
+
+/// Renders the grid lines on the left and top edges of the dots on multiples of the cell size
+/// (within the canvas bounds if any).
+///
+/// The grid is not rendered at scale 1, where the lines would hide the dots.
+fn render_grid(grid: Grid, bounds: Option<(Point, Point)>, screen: &mut Screen) {
+    if screen.scale().get() < 2 {
+        return;
+    }
+    let (mut start, mut end) = screen.visible_range();
+    if let Some((bounds_start, bounds_end)) = bounds {
+        start = Point::new(start.x.max(bounds_start.x), start.y.max(bounds_start.y));
+        end = Point::new(end.x.min(bounds_end.x), end.y.min(bounds_end.y));
+    }
+    if start.x > end.x || start.y > end.y {
+        return;
+    }
+
+    let top_left = screen.point_to_position(start);
+    let bottom_right =
+        screen.point_to_position(end) + Position::from_xy(1, 1) * screen.scale().get() as u32;
+    let size = bottom_right - top_left;
+    for x in start.x..=end.x {
+        if i32::from(x).rem_euclid(i32::from(grid.width.get())) == 0 {
+            let x = screen.point_to_position(Point::new(x, start.y)).x;
+            let line = Size::from_wh(1, size.y as u32);
+            screen.fill_region(
+                Region::new(Position::from_xy(x, top_left.y), line),
+                grid.color,
+            );
+        }
+    }
+    for y in start.y..=end.y {
+        if i32::from(y).rem_euclid(i32::from(grid.height.get())) == 0 {
+            let y = screen.point_to_position(Point::new(start.x, y)).y;
+            let line = Size::from_wh(size.x as u32, 1);
+            screen.fill_region(
+                Region::new(Position::from_xy(top_left.x, y), line),
+                grid.color,
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Drag {
+    /// Whether the drag started a stroke (that is drawn when the button is released).
+    stroke: bool,
+}
+
+/// Renders the cursor (or the marked points) with the brush color.
+pub fn render_cursor(canvas: &Canvas, screen: &mut Screen) {
+    let color = canvas.brush_color();
+    if let Some(marker) = canvas.marker() {
+        for point in marker.marked_points() {
+            screen.dot(point, color);
+        }
+    } else if canvas.editor().is_none() {
+        screen.dot(canvas.cursor(), color);
+    }
+}
+
+/// Renders the pending key input at the bottom-left corner of the screen.
+fn render_pending_keys(text: &str, screen: &mut Screen) {
+    const MARGIN: u32 = 1;
+    let size = Size::from_wh(
+        font::text_width(text) + MARGIN * 2,
+        font::GLYPH_SIZE.height + MARGIN * 2,
+    );
+    let position = Position::from_xy(0, screen.screen_size().height as i32 - size.height as i32);
+    screen.fill_region(Region::new(position, size), Color::rgb(0, 0, 0));
+    let position = position + Position::from_xy(MARGIN as i32, MARGIN as i32);
+    screen.draw_text(position, text, Color::rgb(255, 255, 255));
+}
+
+/// Renders the minibuffer at the bottom of the screen (and the error message above it if any).
+fn render_minibuffer(minibuffer: &Minibuffer, screen: &mut Screen) {
+    const MARGIN: u32 = 1;
+    const LINE_HEIGHT: u32 = font::GLYPH_SIZE.height + MARGIN * 2;
+    let width = screen.screen_size().width;
+    let max_chars = (width.saturating_sub(MARGIN * 2) / font::ADVANCE) as usize;
+    let mut render_line = |line: u32, text: &str, fg: Color, bg: Color| {
+        // Show the tail of the text if it is too long.
+        let skip = text.chars().count().saturating_sub(max_chars);
+        let text = text.chars().skip(skip).collect::<String>();
+        let y = screen.screen_size().height as i32 - (LINE_HEIGHT * (line + 1)) as i32;
+        let region = Region::new(Position::from_xy(0, y), Size::from_wh(width, LINE_HEIGHT));
+        screen.fill_region(region, bg);
+        let position = Position::from_xy(MARGIN as i32, y + MARGIN as i32);
+        screen.draw_text(position, &text, fg);
+    };
+
+    let black = Color::rgb(0, 0, 0);
+    render_line(
+        0,
+        &format!(":{}", minibuffer.input()),
+        Color::rgb(255, 255, 255),
+        black,
+    );
+    if let Some(error) = minibuffer.error() {
+        render_line(1, error, Color::rgb(255, 80, 80), black);
+    }
+}
+
+/// Blinking state of the cursor.
+///
+/// The cursor is kept visible for a while after a key is pressed, and then blinks.
+#[derive(Debug, Default, Clone, Copy)]
+struct CursorBlink {
+    visible: bool,
+    switch_time: Duration,
+}
+
+impl CursorBlink {
+    const KEEP_VISIBLE: Duration = Duration::from_secs(1);
+    const INTERVAL: Duration = Duration::from_millis(500);
+
+    fn reset(&mut self, now: Duration) {
+        self.visible = true;
+        self.switch_time = now + Self::KEEP_VISIBLE;
+    }
+
+    fn tick(&mut self, now: Duration) {
+        if now >= self.switch_time {
+            self.visible = !self.visible;
+            self.switch_time = now + Self::INTERVAL;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pagurus::{
+        image::Canvas as PagurusCanvas,
+        spatial::{Position, Size},
+        video::{PixelFormat, VideoFrame, VideoFrameSpec},
+    };
+    use pati::{Color, ImageCommand, Point};
+    use paticanvas::{CanvasCommand, MarkKind, METADATA_BACKGROUND_COLOR};
+
+    const RED: Color = Color::rgb(255, 0, 0);
+    const GRAY: Color = Color::rgb(200, 200, 200);
+    const BLACK: Color = Color::rgb(0, 0, 0);
+
+    #[test]
+    fn render_camera_scale_and_cursor() {
+        // The screen is 4x3 dots (scale = 2), and the camera (origin) is at the center dot (2,1).
+        let mut canvas = new_canvas();
+        let expected = |x: i32, y: i32| match (x / 2, y / 2) {
+            (3, 1) => RED,
+            _ => GRAY,
+        };
+        assert_eq!(render(&canvas, false), pixels(expected));
+        assert_eq!(
+            render(&canvas, true),
+            pixels(|x, y| if (x / 2, y / 2) == (2, 1) {
+                BLACK
+            } else {
+                expected(x, y)
+            })
+        );
+
+        // Marked points are rendered as the cursor.
+        canvas
+            .command(&CanvasCommand::Mark(MarkKind::Rectangle))
+            .unwrap();
+        canvas
+            .command(&CanvasCommand::Move(Point::new(1, 1)))
+            .unwrap();
+        assert_eq!(
+            render(&canvas, true),
+            pixels(|x, y| if x >= 4 && y >= 2 { BLACK } else { GRAY })
+        );
+    }
+
+    #[test]
+    fn cursor_blink() {
+        let ms = Duration::from_millis;
+        let mut cursor = CursorBlink::default();
+        cursor.reset(ms(0));
+        cursor.tick(ms(500));
+        assert!(cursor.visible);
+        cursor.tick(ms(1000));
+        assert!(!cursor.visible);
+        cursor.tick(ms(1200));
+        assert!(!cursor.visible);
+        cursor.tick(ms(1500));
+        assert!(cursor.visible);
+    }
+
+    fn new_canvas() -> Canvas {
+        let mut canvas = Canvas::new();
+        let background = serde_json::to_value(GRAY).unwrap();
+        let commands = [
+            CanvasCommand::Image(ImageCommand::put(METADATA_BACKGROUND_COLOR, background)),
+            CanvasCommand::Image(ImageCommand::draw_pixels(std::iter::once((
+                Point::new(1, 0),
+                RED,
+            )))),
+            CanvasCommand::Dip(BLACK),
+            CanvasCommand::Scale(1),
+        ];
+        for command in &commands {
+            canvas.command(command).unwrap();
+        }
+        canvas
+    }
+
+    const SIZE: Size = Size::from_wh(8, 6);
+
+    fn pixels(f: impl Fn(i32, i32) -> Color) -> Vec<Vec<Color>> {
+        (0..SIZE.height as i32)
+            .map(|y| (0..SIZE.width as i32).map(|x| f(x, y)).collect())
+            .collect()
+    }
+
+    fn render(canvas: &Canvas, cursor: bool) -> Vec<Vec<Color>> {
+        let mut video_frame = VideoFrame::new(VideoFrameSpec {
+            pixel_format: PixelFormat::Rgb24,
+            resolution: SIZE,
+            stride: SIZE.width,
+        });
+        let mut screen = Screen::new(PagurusCanvas::new(&mut video_frame), SIZE);
+        screen.set_camera(canvas.camera(), canvas.scale());
+        render_canvas(canvas, &mut screen);
+        if cursor {
+            render_cursor(canvas, &mut screen);
+        }
+        pixels(|x, y| {
+            let (r, g, b) = video_frame.read_rgb(Position::from_xy(x, y));
+            Color::rgb(r, g, b)
+        })
+    }
+}